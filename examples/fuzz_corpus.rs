@@ -0,0 +1,51 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+//! Derive the seed corpus in "fuzz/corpus" from "tests/bins". Run this after
+//! changing the fixtures or the format of a seed:
+//!
+//! cargo run --example fuzz_corpus
+
+use elderscroll::{builder::PdbBuilder, dbi::DbiStream, directory::DBI_STREAM_INDEX, msf::BigMsf};
+use std::path::Path;
+
+fn main() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let builder = PdbBuilder::new().page_size(0x200).build().unwrap().bytes;
+    let mut seeds = vec![
+        ("get_stream_directory", "HelloWorld.pdb", bytes.to_vec()),
+        ("get_stream_directory", "PdbBuilder.pdb", builder.clone()),
+        ("roundtrip", "HelloWorld.pdb", bytes.to_vec()),
+        ("roundtrip", "PdbBuilder.pdb", builder),
+    ];
+    // The DBI stream, and the OMAP entries "omap_test1" moves code with.
+    let stream_directory = BigMsf::new(bytes.to_vec()).get_stream_directory().unwrap();
+    let mut dbi = DbiStream::new(stream_directory.streams[DBI_STREAM_INDEX].clone());
+    seeds.push((
+        "extra_streams",
+        "HelloWorld.dbi",
+        dbi.stream.view.bytes.clone(),
+    ));
+    #[allow(deprecated)]
+    dbi.nop_section_maps().unwrap();
+    seeds.push(("extra_streams", "HelloWorld_nop.dbi", dbi.stream.view.bytes));
+    let mut omap = vec![];
+    for pair in [[0x1008u32, 0x1000], [0x100B, 0x1000], [0x109F, 0x109F]] {
+        omap.extend(pair.iter().flat_map(|value| value.to_le_bytes()));
+    }
+    seeds.push(("omap", "HelloWorld.omap", omap));
+
+    for (name, file_name, seed) in seeds {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fuzz/corpus")
+            .join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(file_name);
+        if std::fs::read(&path).ok().as_ref() != Some(&seed) {
+            println!("{}", path.display());
+            std::fs::write(path, &seed).unwrap();
+        }
+    }
+}
@@ -0,0 +1,7 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| elderscroll_fuzz::extra_streams(data));
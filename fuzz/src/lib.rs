@@ -0,0 +1,88 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+//! Bodies of the fuzz targets. They live here instead of in "fuzz_targets" so
+//! "tests/fuzz.rs" can replay the corpus and old crashes without libFuzzer.
+
+use elderscroll::{
+    dbi::DbiStream, directory::Stream, msf::BigMsf, omap::OmapStream, pagelist::PageList,
+    view::SourceView,
+};
+
+/// Body of a fuzz target.
+pub type Target = fn(&[u8]);
+
+/// Every fuzz target by name, the name is also the directory of its corpus.
+pub const TARGETS: &[(&str, Target)] = &[
+    ("get_stream_directory", get_stream_directory),
+    ("extra_streams", extra_streams),
+    ("omap", omap),
+    ("roundtrip", roundtrip),
+];
+
+/// Parse the stream directory of an MSF file.
+pub fn get_stream_directory(data: &[u8]) {
+    let _ = BigMsf::new(data.to_vec()).get_stream_directory();
+}
+
+/// Parse a DBI stream, its substreams, modules, section map and extra streams.
+pub fn extra_streams(data: &[u8]) {
+    let mut dbi = DbiStream::new(Stream {
+        original_stream_size: data.len() as u32,
+        view: SourceView {
+            bytes: data.to_vec(),
+            pages: PageList::default(),
+        },
+    });
+    let _ = dbi.header();
+    let _ = dbi.extra_streams();
+    #[allow(deprecated)]
+    let _ = dbi.nop_section_maps();
+    if let Ok(substreams) = dbi.substreams() {
+        dbi.set_substreams(&substreams).unwrap();
+        // Writing the section map needs every substream, reading it does not.
+        if let Ok(section_map) = dbi.section_map() {
+            dbi.set_section_map(&section_map).unwrap();
+            assert_eq!(dbi.section_map().unwrap(), section_map);
+        }
+    }
+    if let Ok(modules) = dbi.modules() {
+        dbi.set_modules(&modules).unwrap();
+        assert_eq!(dbi.modules().unwrap(), modules);
+    }
+}
+
+/// Parse an OMAP stream, whatever parses writes back to the same entries.
+pub fn omap(data: &[u8]) {
+    if let Ok(omap_stream) = OmapStream::new(data) {
+        let bytes = omap_stream.to_vec().unwrap();
+        assert_eq!(OmapStream::new(&bytes).unwrap().0, omap_stream.0);
+    }
+}
+
+/// Open an MSF file, add, replace and remove streams, save it and open it
+/// again. The saved file must hold exactly the edited streams.
+pub fn roundtrip(data: &[u8]) {
+    let mut msf = BigMsf::new(data.to_vec());
+    let Ok(mut stream_directory) = msf.get_stream_directory() else {
+        return;
+    };
+    let added = stream_directory.add_stream(data[..data.len().min(0x1234)].to_vec());
+    let _ = stream_directory.replace_stream(1, vec![0x69; 0x100]);
+    let _ = stream_directory.remove_stream(added.saturating_sub(1));
+    let expected = stream_directory
+        .streams
+        .iter()
+        .map(|stream| (stream.is_nil(), stream.view.bytes.clone()))
+        .collect::<Vec<(bool, Vec<u8>)>>();
+    if msf.set_stream_directory(stream_directory).is_err() {
+        return;
+    }
+    let streams = msf
+        .get_stream_directory()
+        .expect("A saved file must open again!")
+        .streams
+        .iter()
+        .map(|stream| (stream.is_nil(), stream.view.bytes.clone()))
+        .collect::<Vec<(bool, Vec<u8>)>>();
+    assert!(streams == expected, "Streams changed when saved!");
+}
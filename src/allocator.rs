@@ -0,0 +1,199 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use crate::{
+    fpm::{is_reserved_page, FreePageMap},
+    msf::{page_offset, PageNumber},
+};
+
+/// Hands out pages of the MSF file. Free pages are reused before the file
+/// is extended, and pages which are no longer used can be released.
+#[derive(Debug, Default, Clone)]
+pub struct PageAllocator {
+    /// Tracks which pages are free, this becomes the FPM of the file.
+    pub fpm: FreePageMap,
+    /// Zero pages when they are handed out again and when they end up free.
+    pub zero_fill: bool,
+    /// Pages used by the last committed state of the file. These are never handed
+    /// out (or zeroed) so the committed state stays intact until the header is written.
+    pub committed: Option<FreePageMap>,
+    /// Lowest page number which might be free.
+    next_free: PageNumber,
+}
+
+impl PageAllocator {
+    /// Create an allocator where every page except the reserved ones is free.
+    /// Pages that are in use must be marked with "mark_used" before allocating.
+    pub fn new(num_pages: u32, page_size: u32) -> Self {
+        Self {
+            fpm: FreePageMap::new(num_pages, page_size),
+            zero_fill: false,
+            committed: None,
+            next_free: 0,
+        }
+    }
+    /// Page size of the MSF file.
+    #[inline(always)]
+    pub fn page_size(&self) -> u32 {
+        self.fpm.page_size
+    }
+    /// Number of pages in the MSF file, this grows as pages are allocated.
+    #[inline(always)]
+    pub fn num_pages(&self) -> u32 {
+        self.fpm.num_pages
+    }
+    /// How many pages are required to store N amount of bytes?
+    #[inline(always)]
+    pub fn pages_needed_to_store(&self, bytes: usize) -> usize {
+        bytes.div_ceil(self.page_size() as usize)
+    }
+    /// Mark a page as used so that it is never handed out.
+    #[inline(always)]
+    pub fn mark_used(&mut self, pfn: PageNumber) {
+        self.fpm.mark_used(pfn);
+    }
+    /// Give a page back to the allocator so it can be reused.
+    pub fn release(&mut self, pfn: PageNumber) {
+        if !is_reserved_page(pfn, self.page_size()) {
+            self.fpm.mark_free(pfn);
+            self.next_free = std::cmp::min(self.next_free, pfn);
+        }
+    }
+    /// Can the page be handed out? It must be free now and in the committed state.
+    #[inline(always)]
+    pub fn is_available(&self, pfn: PageNumber) -> bool {
+        self.fpm.is_free(pfn)
+            && self
+                .committed
+                .as_ref()
+                .is_none_or(|committed| pfn >= committed.num_pages || committed.is_free(pfn))
+    }
+    /// Allocate a single page and keep "buff" the size of the file. Reused pages
+    /// are zeroed if "zero_fill" is set.
+    pub fn allocate(&mut self, buff: &mut Vec<u8>) -> PageNumber {
+        let num_pages = self.num_pages();
+        let pfn = self.allocate_page();
+        if pfn >= num_pages {
+            buff.resize(page_offset(self.num_pages(), self.page_size()) as usize, 0);
+        } else if self.zero_fill {
+            let page_start = page_offset(pfn, self.page_size()) as usize;
+            buff[page_start..page_start + self.page_size() as usize].fill(0);
+        }
+        pfn
+    }
+    /// Allocate a single page. The lowest free page is reused first, if there is
+    /// none the file is extended (skipping the FPM pages of any new interval).
+    pub fn allocate_page(&mut self) -> PageNumber {
+        while self.next_free < self.num_pages() {
+            let pfn = self.next_free;
+            self.next_free += 1;
+            if self.is_available(pfn) {
+                self.fpm.mark_used(pfn);
+                return pfn;
+            }
+        }
+        // No free pages left, extend the file.
+        let mut pfn = self.num_pages();
+        while is_reserved_page(pfn, self.page_size()) {
+            pfn += 1;
+        }
+        self.grow(pfn + 1);
+        self.fpm.mark_used(pfn);
+        self.next_free = pfn + 1;
+        pfn
+    }
+    /// Grow the file to "num_pages", new pages are free unless they are reserved.
+    pub fn grow(&mut self, num_pages: u32) {
+        let old_num_pages = self.num_pages();
+        if num_pages <= old_num_pages {
+            return;
+        }
+        self.fpm.num_pages = num_pages;
+        self.fpm.bits.resize(num_pages.div_ceil(8) as usize, 0xFF);
+        for pfn in old_num_pages..num_pages {
+            if is_reserved_page(pfn, self.page_size()) {
+                self.fpm.mark_used(pfn);
+            } else {
+                self.fpm.mark_free(pfn);
+            }
+        }
+    }
+    /// Zero every free page in the file, pages of the committed state are kept.
+    pub fn zero_free_pages(&self, buff: &mut [u8]) {
+        let page_size = self.page_size() as usize;
+        for pfn in 0..self.num_pages() {
+            if self.is_available(pfn) {
+                let page_start = page_offset(pfn, self.page_size()) as usize;
+                buff[page_start..page_start + page_size].fill(0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PageAllocator;
+    use crate::fpm::FreePageMap;
+
+    /// Released pages are handed out before the file grows.
+    #[test]
+    fn allocator_reuse() {
+        let mut buff = vec![0x69u8; 0x6000];
+        let mut allocator = PageAllocator::new(6, 0x1000);
+        for pfn in 3..6 {
+            allocator.mark_used(pfn);
+        }
+        allocator.release(4);
+        assert_eq!(allocator.allocate(&mut buff), 4);
+        assert_eq!(allocator.allocate(&mut buff), 6);
+        assert_eq!(allocator.num_pages(), 7);
+        assert_eq!(buff.len(), 0x7000);
+        // Reserved pages can never be released.
+        allocator.release(1);
+        assert!(!allocator.fpm.is_free(1));
+    }
+
+    /// Growing into a new interval must skip its FPM pages.
+    #[test]
+    fn allocator_skip_fpm() {
+        let mut buff = vec![0u8; 0x200 * 0x200];
+        let mut allocator = PageAllocator::new(0x200, 0x200);
+        for pfn in 0..0x200 {
+            allocator.mark_used(pfn);
+        }
+        assert_eq!(allocator.allocate(&mut buff), 0x200);
+        assert_eq!(allocator.allocate(&mut buff), 0x203);
+        assert_eq!(allocator.num_pages(), 0x204);
+    }
+
+    /// Pages of the committed state are not handed out even when they are free.
+    #[test]
+    fn allocator_committed() {
+        let mut buff = vec![0x69u8; 0x6000];
+        let mut committed = FreePageMap::new(6, 0x1000);
+        committed.mark_used(3);
+        committed.mark_used(4);
+        let mut allocator = PageAllocator::new(6, 0x1000);
+        allocator.committed = Some(committed);
+        allocator.zero_fill = true;
+        assert_eq!(allocator.allocate(&mut buff), 5);
+        assert_eq!(allocator.allocate(&mut buff), 6);
+        allocator.zero_free_pages(&mut buff);
+        assert!(buff[0x3000..0x5000].iter().all(|&e| e == 0x69));
+    }
+
+    /// Zero fill clears reused pages and free pages.
+    #[test]
+    fn allocator_zero_fill() {
+        let mut buff = vec![0x69u8; 0x5000];
+        let mut allocator = PageAllocator::new(5, 0x1000);
+        allocator.zero_fill = true;
+        allocator.mark_used(4);
+        assert_eq!(allocator.allocate(&mut buff), 3);
+        assert!(buff[0x3000..0x4000].iter().all(|&e| e == 0));
+        allocator.release(4);
+        allocator.zero_free_pages(&mut buff);
+        assert!(buff[0x4000..0x5000].iter().all(|&e| e == 0));
+        // Reserved pages are not touched.
+        assert!(buff[0x1000..0x2000].iter().all(|&e| e == 0x69));
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use crate::{
+    dbi::{DbiExtraStreamOverlay, DbiStreamHeaderOverlay, DbiStreamHeaderOverlayMut},
+    directory::{StreamDirectory, INVALID_STREAM_INDEX},
+    error::Error,
+    msf::{BigMsf, DEFAULT_PAGE_SIZE},
+    namedstreammap::NamedStreamMap,
+    pdbinfo::{PdbFeature, PdbInfoStream},
+    stringtable::StringTable,
+    tpi::{
+        TpiStreamHeaderOverlay, TpiStreamHeaderOverlayMut, TPI_FIRST_TYPE_INDEX, TPI_VERSION_V80,
+    },
+};
+
+pub use crate::pdbinfo::{PDB_FEATURE_VC140, PDB_INFO_VERSION_VC70};
+pub use crate::stringtable::STRING_TABLE_SIGNATURE;
+
+/// Version signature of the "new" DBI stream format.
+pub const DBI_VERSION_SIGNATURE: u32 = u32::MAX;
+/// Version of the DBI stream written by VC 7.0 and newer.
+pub const DBI_VERSION_V70: u32 = 19990903;
+/// Version of the section contribution substream written by VC 6.0 and newer.
+pub const SECTION_CONTRIBUTION_VERSION_V60: u32 = 0xF12EBA2D;
+/// Machine type of x64 images.
+pub const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+/// Stream index of the "/names" stream in the PDB files we build.
+pub const NAMES_STREAM_INDEX: usize = 5;
+
+/// Creates a brand new PDB with the minimal streams every PDB has: the old
+/// stream directory, PDB info, TPI, DBI, IPI and "/names".
+#[derive(Debug, Clone)]
+pub struct PdbBuilder {
+    /// Page size of the MSF file.
+    pub page_size: u32,
+    /// Signature in the PDB info stream, link.exe uses a timestamp.
+    pub signature: u32,
+    /// Age in the PDB info and DBI streams, must match the RSDS debug directory.
+    pub age: u32,
+    /// GUID in the PDB info stream, must match the RSDS debug directory.
+    pub guid: [u8; 16],
+    /// Machine type in the DBI stream.
+    pub machine: u16,
+}
+
+impl Default for PdbBuilder {
+    fn default() -> Self {
+        Self {
+            page_size: DEFAULT_PAGE_SIZE,
+            signature: 0,
+            age: 1,
+            guid: [0u8; 16],
+            machine: IMAGE_FILE_MACHINE_AMD64,
+        }
+    }
+}
+
+impl PdbBuilder {
+    /// Create a builder for an x64 PDB with 4KiB pages and an age of 1.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Set the page size of the MSF file.
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.page_size = page_size;
+        self
+    }
+    /// Set the signature of the PDB info stream.
+    pub fn signature(mut self, signature: u32) -> Self {
+        self.signature = signature;
+        self
+    }
+    /// Set the age of the PDB info and DBI streams.
+    pub fn age(mut self, age: u32) -> Self {
+        self.age = age;
+        self
+    }
+    /// Set the GUID of the PDB info stream.
+    pub fn guid(mut self, guid: [u8; 16]) -> Self {
+        self.guid = guid;
+        self
+    }
+    /// Set the machine type of the DBI stream.
+    pub fn machine(mut self, machine: u16) -> Self {
+        self.machine = machine;
+        self
+    }
+    /// Build the MSF file with all of the minimal streams.
+    pub fn build(&self) -> Result<BigMsf, Error> {
+        let mut dir = StreamDirectory::default();
+        // The old stream directory is empty.
+        dir.add_stream(Vec::new());
+        dir.add_stream(self.pdb_info_stream()?);
+        dir.add_stream(Self::tpi_stream()?);
+        dir.add_stream(self.dbi_stream()?);
+        dir.add_stream(Self::tpi_stream()?);
+        dir.add_stream(StringTable::default().to_vec()?);
+        BigMsf::from_stream_directory(self.page_size, dir)
+    }
+    /// https://llvm.org/docs/PDB/PdbStream.html
+    /// The named stream map only maps "/names" to its stream.
+    fn pdb_info_stream(&self) -> Result<Vec<u8>, Error> {
+        let mut named_stream_map = NamedStreamMap::default();
+        named_stream_map.insert("/names", NAMES_STREAM_INDEX as u32);
+        PdbInfoStream {
+            version: PDB_INFO_VERSION_VC70,
+            signature: self.signature,
+            age: self.age,
+            guid: self.guid,
+            named_stream_map,
+            features: vec![PdbFeature::Vc140],
+        }
+        .to_vec()
+    }
+    /// https://llvm.org/docs/PDB/TpiStream.html
+    /// A TPI or IPI stream without any type records and without a hash stream.
+    fn tpi_stream() -> Result<Vec<u8>, Error> {
+        let mut bytes = vec![0u8; TpiStreamHeaderOverlay::size()];
+        let mut header =
+            TpiStreamHeaderOverlayMut::new(&mut bytes).ok_or(Error::TruncatedHeader("TPI"))?;
+        header.set_version(TPI_VERSION_V80);
+        header.set_header_size(TpiStreamHeaderOverlay::size() as u32);
+        header.set_type_index_begin(TPI_FIRST_TYPE_INDEX);
+        header.set_type_index_end(TPI_FIRST_TYPE_INDEX);
+        header.set_hash_stream_index(INVALID_STREAM_INDEX);
+        header.set_hash_aux_stream_index(INVALID_STREAM_INDEX);
+        header.set_hash_key_size(4);
+        header.set_num_hash_buckets(0x3FFFF);
+        Ok(bytes)
+    }
+    /// https://llvm.org/docs/PDB/DbiStream.html
+    /// A DBI stream without modules, every optional debug stream is missing.
+    fn dbi_stream(&self) -> Result<Vec<u8>, Error> {
+        let section_contributions = SECTION_CONTRIBUTION_VERSION_V60.to_le_bytes();
+        // Count and LogCount of the section map.
+        let section_map = [0u8; 4];
+        // NumModules and NumSourceFiles of the file info.
+        let file_info = [0u8; 4];
+        let ec = StringTable::default().to_vec()?;
+        let optional_dbg_header = [0xFFu8; DbiExtraStreamOverlay::size()];
+        let mut bytes = vec![0u8; DbiStreamHeaderOverlay::size()];
+        let mut header =
+            DbiStreamHeaderOverlayMut::new(&mut bytes).ok_or(Error::TruncatedHeader("DBI"))?;
+        header.set_version(DBI_VERSION_SIGNATURE);
+        header.set_version_header(DBI_VERSION_V70);
+        header.set_age(self.age);
+        header.set_global_stream_index(INVALID_STREAM_INDEX);
+        // New version format, toolchain 14.0
+        header.set_build_number(0x8E00);
+        header.set_public_stream_index(INVALID_STREAM_INDEX);
+        header.set_sym_record_stream(INVALID_STREAM_INDEX);
+        header.set_mod_info_size(0);
+        header.set_section_contribution_size(section_contributions.len() as u32);
+        header.set_section_map_size(section_map.len() as u32);
+        header.set_source_info_size(file_info.len() as u32);
+        header.set_type_server_map_size(0);
+        header.set_optional_dbg_header_size(optional_dbg_header.len() as u32);
+        header.set_ec_substream_size(ec.len() as u32);
+        header.set_machine(self.machine);
+        bytes.extend_from_slice(&section_contributions);
+        bytes.extend_from_slice(&section_map);
+        bytes.extend_from_slice(&file_info);
+        bytes.extend_from_slice(&ec);
+        bytes.extend_from_slice(&optional_dbg_header);
+        Ok(bytes)
+    }
+}
@@ -1,7 +1,16 @@
 // Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
 
-use crate::{directory::Stream, struct_overlay_both};
-use scroll::{Error, Pwrite};
+use crate::{
+    directory::{
+        Stream, StreamDirectory, DBI_STREAM_INDEX, INVALID_STREAM_INDEX, IPI_STREAM_INDEX,
+    },
+    error::Error,
+    modinfo::{ModuleInfo, ModuleInfoIter},
+    omap::OmapStream,
+    sectionmap::{SectionHeader, SectionMap},
+    struct_overlay_both,
+};
+use scroll::{Pread, Pwrite};
 use static_assertions::const_assert;
 
 // https://llvm.org/docs/PDB/DbiStream.html#stream-header
@@ -45,6 +54,78 @@ struct_overlay_both!((pub DbiExtraStreamOverlay, pub DbiExtraStreamOverlayMut) {
 });
 const_assert!(DbiExtraStreamOverlay::size() == 0x16);
 
+/// Optional debug streams, in the order of their indices in the optional
+/// debug header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugStreamKind {
+    Fpo,
+    Exception,
+    Fixup,
+    OmapToSrc,
+    OmapFromSrc,
+    SectionHeaders,
+    TokenRidMap,
+    Xdata,
+    Pdata,
+    NewFpo,
+    OriginalSectionHeaders,
+}
+
+impl DebugStreamKind {
+    /// Every kind of optional debug stream.
+    pub const ALL: [Self; 11] = [
+        Self::Fpo,
+        Self::Exception,
+        Self::Fixup,
+        Self::OmapToSrc,
+        Self::OmapFromSrc,
+        Self::SectionHeaders,
+        Self::TokenRidMap,
+        Self::Xdata,
+        Self::Pdata,
+        Self::NewFpo,
+        Self::OriginalSectionHeaders,
+    ];
+    /// Offset of the stream index in the optional debug header.
+    fn offset(self) -> usize {
+        self as usize * 2
+    }
+}
+
+/// The substreams of the DBI stream after its header, in the order they are
+/// stored in. Every substream can be edited, grown or shrunk, "set_substreams"
+/// writes them back.
+/// https://llvm.org/docs/PDB/DbiStream.html
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DbiSubstreams {
+    pub module_info: Vec<u8>,
+    pub section_contributions: Vec<u8>,
+    pub section_map: Vec<u8>,
+    pub file_info: Vec<u8>,
+    pub type_server_map: Vec<u8>,
+    /// A string table of the names of the object files compiled with /EC.
+    pub ec: Vec<u8>,
+    /// Stream indices of the optional debug streams.
+    pub optional_dbg_header: Vec<u8>,
+}
+
+impl DbiSubstreams {
+    /// Iterate over the modules of the module info substream.
+    pub fn modules(&self) -> ModuleInfoIter<'_> {
+        ModuleInfoIter::new(&self.module_info)
+    }
+    /// Replace the module info substream. Section contributions refer to
+    /// modules by index, so modules should only be added at the end.
+    pub fn set_modules(&mut self, modules: &[ModuleInfo]) -> Result<(), Error> {
+        let mut module_info = Vec::new();
+        for module in modules {
+            module.write(&mut module_info)?;
+        }
+        self.module_info = module_info;
+        Ok(())
+    }
+}
+
 /// High level abstraction of the DBI stream.
 #[derive(Debug, Default, Clone)]
 pub struct DbiStream {
@@ -57,27 +138,143 @@ impl DbiStream {
     pub fn new(stream: Stream) -> Self {
         Self { stream }
     }
+    /// Read the DBI stream of a stream directory.
+    pub fn from_stream_directory(dir: &StreamDirectory) -> Result<Self, Error> {
+        Ok(Self::new(dir.stream(DBI_STREAM_INDEX)?.clone()))
+    }
+    /// Write the DBI stream back into the directory.
+    pub fn flush(&self, dir: &mut StreamDirectory) -> Result<(), Error> {
+        dir.replace_stream(DBI_STREAM_INDEX, self.stream.view.bytes.clone())
+    }
     /// Get a read-only DbiStreamHeader.
-    pub fn header(&self) -> Option<DbiStreamHeaderOverlay<'_>> {
+    pub fn header(&self) -> Result<DbiStreamHeaderOverlay<'_>, Error> {
+        if self.stream.is_nil() {
+            return Err(Error::MissingDbiStream);
+        }
         DbiStreamHeaderOverlay::new(self.stream.view.as_slice())
+            .ok_or(Error::TruncatedHeader("DBI"))
     }
     /// Get a mutable DbiStreamHeader.
-    pub fn header_mut(&mut self) -> Option<DbiStreamHeaderOverlayMut<'_>> {
+    pub fn header_mut(&mut self) -> Result<DbiStreamHeaderOverlayMut<'_>, Error> {
+        if self.stream.is_nil() {
+            return Err(Error::MissingDbiStream);
+        }
         DbiStreamHeaderOverlayMut::new(self.stream.view.as_mut_slice())
+            .ok_or(Error::TruncatedHeader("DBI"))
+    }
+    /// Offset of a substream which must fit "size" bytes, "preceding" is the
+    /// total size of the substreams before it.
+    fn substream_offset(
+        &self,
+        substream: &'static str,
+        preceding: u64,
+        size: usize,
+    ) -> Result<usize, Error> {
+        let offset = DbiStreamHeaderOverlay::size() as u64 + preceding;
+        if offset + size as u64 > self.stream.view.bytes.len() as u64 {
+            return Err(Error::SubstreamOutOfBounds {
+                substream,
+                offset: offset as usize,
+                size,
+            });
+        }
+        Ok(offset as usize)
+    }
+    /// Split the DBI stream into its substreams.
+    pub fn substreams(&self) -> Result<DbiSubstreams, Error> {
+        let header = self.header()?;
+        let mut preceding = 0u64;
+        let mut next = |substream: &'static str, size: u32| -> Result<Vec<u8>, Error> {
+            let offset = self.substream_offset(substream, preceding, size as usize)?;
+            preceding += size as u64;
+            Ok(self.stream.view.bytes[offset..offset + size as usize].to_vec())
+        };
+        Ok(DbiSubstreams {
+            module_info: next("module info", header.get_mod_info_size())?,
+            section_contributions: next(
+                "section contribution",
+                header.get_section_contribution_size(),
+            )?,
+            section_map: next("section map", header.get_section_map_size())?,
+            file_info: next("file info", header.get_source_info_size())?,
+            type_server_map: next("type server map", header.get_type_server_map_size())?,
+            ec: next("EC", header.get_ec_substream_size())?,
+            optional_dbg_header: next(
+                "optional debug header",
+                header.get_optional_dbg_header_size(),
+            )?,
+        })
+    }
+    /// Replace every substream and recompute their sizes in the header. The
+    /// substreams MSPDB aligns are padded to 4 bytes, the EC substream and the
+    /// optional debug header are not.
+    pub fn set_substreams(&mut self, substreams: &DbiSubstreams) -> Result<(), Error> {
+        self.header()?;
+        let mut bytes = self.stream.view.as_slice()[..DbiStreamHeaderOverlay::size()].to_vec();
+        let mut sizes = Vec::new();
+        for (substream, align) in [
+            (&substreams.module_info, 4),
+            (&substreams.section_contributions, 4),
+            (&substreams.section_map, 4),
+            (&substreams.file_info, 4),
+            (&substreams.type_server_map, 4),
+            (&substreams.ec, 1),
+            (&substreams.optional_dbg_header, 1),
+        ] {
+            let start = bytes.len();
+            bytes.extend_from_slice(substream);
+            bytes.resize(start + substream.len().next_multiple_of(align), 0);
+            sizes.push((bytes.len() - start) as u32);
+        }
+        let mut header =
+            DbiStreamHeaderOverlayMut::new(&mut bytes).ok_or(Error::TruncatedHeader("DBI"))?;
+        header.set_mod_info_size(sizes[0]);
+        header.set_section_contribution_size(sizes[1]);
+        header.set_section_map_size(sizes[2]);
+        header.set_source_info_size(sizes[3]);
+        header.set_type_server_map_size(sizes[4]);
+        header.set_ec_substream_size(sizes[5]);
+        header.set_optional_dbg_header_size(sizes[6]);
+        self.stream.view.bytes = bytes;
+        Ok(())
+    }
+    /// Every module of the module info substream.
+    pub fn modules(&self) -> Result<Vec<ModuleInfo>, Error> {
+        self.substreams()?.modules().collect()
+    }
+    /// Replace every module, for example after renaming object files or
+    /// pointing modules at new symbol streams.
+    pub fn set_modules(&mut self, modules: &[ModuleInfo]) -> Result<(), Error> {
+        let mut substreams = self.substreams()?;
+        substreams.set_modules(modules)?;
+        self.set_substreams(&substreams)
+    }
+    /// Parse the section map substream.
+    pub fn section_map(&self) -> Result<SectionMap, Error> {
+        let header = self.header()?;
+        let preceding =
+            header.get_mod_info_size() as u64 + header.get_section_contribution_size() as u64;
+        let size = header.get_section_map_size() as usize;
+        let offset = self.substream_offset("section map", preceding, size)?;
+        SectionMap::new(&self.stream.view.bytes[offset..offset + size])
+    }
+    /// Replace the section map substream.
+    pub fn set_section_map(&mut self, section_map: &SectionMap) -> Result<(), Error> {
+        let mut substreams = self.substreams()?;
+        substreams.section_map = section_map.to_vec()?;
+        self.set_substreams(&substreams)
     }
     /// This sets the section map descriptor counts to 0
     /// https://github.com/getsentry/pdb/issues/17#issuecomment-2055784958
     /// https://github.com/getsentry/pdb/issues/17#issuecomment-2058271400
     /// https://llvm.org/docs/PDB/DbiStream.html#section-map-substream
     /// Sets "Count" and "LogCount" to 0
+    #[deprecated(note = "use \"rebuild_section_map\", which keeps address translation working")]
     pub fn nop_section_maps(&mut self) -> Result<(), Error> {
-        let dbi_header = self
-            .header()
-            .ok_or_else(|| Error::Custom(format!("Failed to get DbiStreamHeader!")))?;
-
-        let mut offset = (DbiStreamHeaderOverlay::size() as u32
-            + (dbi_header.get_mod_info_size() + dbi_header.get_section_contribution_size()))
-            as usize;
+        let dbi_header = self.header()?;
+        let preceding = dbi_header.get_mod_info_size() as u64
+            + dbi_header.get_section_contribution_size() as u64;
+        let mut offset = self.substream_offset("section map", preceding, 4)?;
 
         // Count = 0
         self.stream
@@ -92,34 +289,172 @@ impl DbiStream {
             .gwrite::<u16>(0, &mut offset)?;
         Ok(())
     }
-    /// Get the read only extra streams.
-    pub fn extra_streams(&self) -> Option<DbiExtraStreamOverlay<'_>> {
+    /// Rebuild the section map from the section headers, so debuggers can
+    /// still translate segment offsets to sections. Symbols keep the addresses
+    /// of the original layout, so the original section headers are used if
+    /// the PDB has them.
+    /// https://llvm.org/docs/PDB/DbiStream.html#section-map-substream
+    pub fn rebuild_section_map(&mut self, dir: &StreamDirectory) -> Result<(), Error> {
+        let section_headers =
+            match self.debug_stream(dir, DebugStreamKind::OriginalSectionHeaders)? {
+                Some(bytes) => bytes,
+                None => self
+                    .debug_stream(dir, DebugStreamKind::SectionHeaders)?
+                    .ok_or(Error::MissingDebugStream("section headers"))?,
+            };
+        let section_headers = SectionHeader::read_all(section_headers)?;
+        self.set_section_map(&SectionMap::from_section_headers(&section_headers))
+    }
+    /// Offset of the DbiExtraStream, it is after all of the other substreams.
+    fn extra_streams_offset(&self) -> Result<usize, Error> {
         let header = self.header()?;
-        // Offset of the DbiExtraStream is after all of these substreams.
-        let offset = DbiStreamHeaderOverlay::size()
-            + (header.get_mod_info_size()
-                + header.get_section_contribution_size()
-                + header.get_section_map_size()
-                + header.get_source_info_size()
-                + header.get_type_server_map_size()
-                + header.get_ec_substream_size()) as usize;
-        Some(DbiExtraStreamOverlay::new(
-            &self.stream.view.as_slice()[offset..],
-        )?)
+        let preceding = [
+            header.get_mod_info_size(),
+            header.get_section_contribution_size(),
+            header.get_section_map_size(),
+            header.get_source_info_size(),
+            header.get_type_server_map_size(),
+            header.get_ec_substream_size(),
+        ]
+        .iter()
+        .map(|size| *size as u64)
+        .sum::<u64>();
+        self.substream_offset(
+            "optional debug header",
+            preceding,
+            DbiExtraStreamOverlay::size(),
+        )
+    }
+    /// Get the read only extra streams.
+    pub fn extra_streams(&self) -> Result<DbiExtraStreamOverlay<'_>, Error> {
+        let offset = self.extra_streams_offset()?;
+        DbiExtraStreamOverlay::new(&self.stream.view.as_slice()[offset..])
+            .ok_or(Error::TruncatedHeader("DBI optional debug"))
     }
     /// Get a mutable extra streams.
-    pub fn extra_streams_mut(&mut self) -> Option<DbiExtraStreamOverlayMut<'_>> {
-        let header = self.header()?;
-        // Offset of the DbiExtraStream is after all of these substreams.
-        let offset = DbiStreamHeaderOverlay::size()
-            + (header.get_mod_info_size()
-                + header.get_section_contribution_size()
-                + header.get_section_map_size()
-                + header.get_source_info_size()
-                + header.get_type_server_map_size()
-                + header.get_ec_substream_size()) as usize;
-        Some(DbiExtraStreamOverlayMut::new(
-            &mut self.stream.view.as_mut_slice()[offset..],
-        )?)
+    pub fn extra_streams_mut(&mut self) -> Result<DbiExtraStreamOverlayMut<'_>, Error> {
+        let offset = self.extra_streams_offset()?;
+        DbiExtraStreamOverlayMut::new(&mut self.stream.view.as_mut_slice()[offset..])
+            .ok_or(Error::TruncatedHeader("DBI optional debug"))
+    }
+    /// Stream index of an optional debug stream, None if it is missing.
+    pub fn debug_stream_index(&self, kind: DebugStreamKind) -> Result<Option<usize>, Error> {
+        let offset = self.extra_streams_offset()? + kind.offset();
+        let index = self.stream.view.as_slice().pread::<u16>(offset)?;
+        Ok((index != INVALID_STREAM_INDEX).then_some(index as usize))
+    }
+    /// Point an optional debug stream at a stream, None marks it as missing.
+    pub fn set_debug_stream_index(
+        &mut self,
+        kind: DebugStreamKind,
+        index: Option<usize>,
+    ) -> Result<(), Error> {
+        let index = match index {
+            Some(index) => u16::try_from(index)
+                .ok()
+                .filter(|index| *index != INVALID_STREAM_INDEX)
+                .ok_or(Error::StreamIndexTooLarge(index))?,
+            None => INVALID_STREAM_INDEX,
+        };
+        let offset = self.extra_streams_offset()? + kind.offset();
+        self.stream
+            .view
+            .as_mut_slice()
+            .pwrite::<u16>(index, offset)?;
+        Ok(())
+    }
+    /// Bytes of an optional debug stream, None if it is missing or nil.
+    pub fn debug_stream<'a>(
+        &self,
+        dir: &'a StreamDirectory,
+        kind: DebugStreamKind,
+    ) -> Result<Option<&'a [u8]>, Error> {
+        let Some(index) = self.debug_stream_index(kind)? else {
+            return Ok(None);
+        };
+        let stream = dir.stream(index)?;
+        Ok((!stream.is_nil()).then_some(stream.view.as_slice()))
+    }
+    /// Can the stream of "kind" be replaced without changing any other stream?
+    /// Streams aliased by another kind, like the original section headers often
+    /// are, and the fixed streams can not.
+    fn owns_debug_stream(&self, kind: DebugStreamKind, index: usize) -> Result<bool, Error> {
+        for other in DebugStreamKind::ALL
+            .into_iter()
+            .filter(|other| *other != kind)
+        {
+            if self.debug_stream_index(other)? == Some(index) {
+                return Ok(false);
+            }
+        }
+        Ok(index > IPI_STREAM_INDEX)
+    }
+    /// Write an optional debug stream. Its stream is reused if it has one of
+    /// its own, otherwise a new stream is added. Returns the stream index, the
+    /// DBI stream must be flushed for a new index to be saved.
+    pub fn write_debug_stream(
+        &mut self,
+        dir: &mut StreamDirectory,
+        kind: DebugStreamKind,
+        bytes: Vec<u8>,
+    ) -> Result<usize, Error> {
+        if let Some(index) = self.debug_stream_index(kind)? {
+            if index < dir.streams.len() && self.owns_debug_stream(kind, index)? {
+                dir.replace_stream(index, bytes)?;
+                return Ok(index);
+            }
+        }
+        let index = dir.add_stream(bytes);
+        self.set_debug_stream_index(kind, Some(index))?;
+        Ok(index)
+    }
+    /// Mark an optional debug stream as missing, its stream is removed unless
+    /// another kind still uses it.
+    pub fn remove_debug_stream(
+        &mut self,
+        dir: &mut StreamDirectory,
+        kind: DebugStreamKind,
+    ) -> Result<(), Error> {
+        if let Some(index) = self.debug_stream_index(kind)? {
+            if index < dir.streams.len() && self.owns_debug_stream(kind, index)? {
+                dir.remove_stream(index)?;
+            }
+        }
+        self.set_debug_stream_index(kind, None)
+    }
+    /// Read the OMAP streams, (to source, from source).
+    pub fn omap(
+        &self,
+        dir: &StreamDirectory,
+    ) -> Result<(Option<OmapStream>, Option<OmapStream>), Error> {
+        let read = |kind| -> Result<Option<OmapStream>, Error> {
+            self.debug_stream(dir, kind)?
+                .map(OmapStream::new)
+                .transpose()
+        };
+        Ok((
+            read(DebugStreamKind::OmapToSrc)?,
+            read(DebugStreamKind::OmapFromSrc)?,
+        ))
+    }
+    /// Write both OMAP streams, reusing the existing ones. Debuggers only apply
+    /// OMAP with original section headers, PDBs without them get the section
+    /// headers stream as their original section headers.
+    pub fn set_omap(
+        &mut self,
+        dir: &mut StreamDirectory,
+        to_src: &OmapStream,
+        from_src: &OmapStream,
+    ) -> Result<(), Error> {
+        self.write_debug_stream(dir, DebugStreamKind::OmapToSrc, to_src.to_vec()?)?;
+        self.write_debug_stream(dir, DebugStreamKind::OmapFromSrc, from_src.to_vec()?)?;
+        if self
+            .debug_stream_index(DebugStreamKind::OriginalSectionHeaders)?
+            .is_none()
+        {
+            let section_headers = self.debug_stream_index(DebugStreamKind::SectionHeaders)?;
+            self.set_debug_stream_index(DebugStreamKind::OriginalSectionHeaders, section_headers)?;
+        }
+        Ok(())
     }
 }
@@ -1,16 +1,52 @@
 // Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
 
 use crate::{
-    msf::{MsfBigHeader, MsfBigHeaderMut},
+    allocator::PageAllocator,
+    error::Error,
+    fpm::is_reserved_page,
+    msf::{MsfBigHeader, MsfBigHeaderMut, PageNumber, BLOCK_MAP_OFFSET},
     pagelist::PageList,
     view::SourceView,
 };
-use scroll::{Error, Pread, Pwrite};
+use scroll::{Pread, Pwrite};
+use std::collections::HashSet;
 
 /// This is the constant for invalid stream indices.
 pub const INVALID_STREAM_INDEX: u16 = 0xFFFF;
 pub const INVALID_STREAM_SIZE: u32 = u32::MAX;
+pub const PDB_INFO_STREAM_INDEX: usize = 1;
+pub const TPI_STREAM_INDEX: usize = 2;
 pub const DBI_STREAM_INDEX: usize = 3;
+pub const IPI_STREAM_INDEX: usize = 4;
+
+/// Write the stream directory into "buff": the number of streams, the size of
+/// each stream, then the PFN's of each stream.
+pub fn write_directory<'a>(
+    buff: &mut [u8],
+    streams: impl Iterator<Item = (u32, &'a [PageNumber])> + Clone,
+) -> Result<(), Error> {
+    let mut offset = 0;
+    // Write the number of streams (NumStreams)
+    buff.gwrite::<u32>(streams.clone().count() as u32, &mut offset)?;
+    // Write each streams size now.
+    for (size, _) in streams.clone() {
+        buff.gwrite::<u32>(size, &mut offset)?;
+    }
+    // Write each streams pfn now.
+    for (_, pfns) in streams {
+        for pfn in pfns {
+            buff.gwrite::<u32>(*pfn, &mut offset)?;
+        }
+    }
+    Ok(())
+}
+
+/// Read a u32 of the stream directory, running out of bytes means it is truncated.
+#[inline(always)]
+pub(crate) fn read_u32(buff: &[u8], offset: &mut usize) -> Result<u32, Error> {
+    buff.gread::<u32>(offset)
+        .map_err(|_| Error::TruncatedDirectory { offset: *offset })
+}
 
 /// Abstraction of the stream itself.
 #[derive(Debug, Default, Clone)]
@@ -22,6 +58,33 @@ pub struct Stream {
     pub view: SourceView,
 }
 
+impl Stream {
+    /// Turn a page of this stream past the end of the file into the offset
+    /// where the stream is cut off.
+    pub(crate) fn truncated(&self, index: usize, e: Error) -> Error {
+        match e {
+            Error::BadPageNumber { pfn, .. } => Error::TruncatedStream {
+                index,
+                offset: self
+                    .view
+                    .pages
+                    .pfns
+                    .iter()
+                    .position(|page| *page == pfn)
+                    .unwrap_or_default()
+                    * self.view.pages.page_size as usize,
+            },
+            e => e,
+        }
+    }
+    /// Is this a nil stream? Nil streams do not exist, which is different from
+    /// a stream with a size of 0.
+    #[inline(always)]
+    pub fn is_nil(&self) -> bool {
+        self.original_stream_size == INVALID_STREAM_SIZE && self.view.bytes.is_empty()
+    }
+}
+
 /// Abstraction of the stream directory.
 #[derive(Debug, Default, Clone)]
 pub struct StreamDirectory {
@@ -30,20 +93,49 @@ pub struct StreamDirectory {
     pub streams: Vec<Stream>,
     /// Linear mapping of the pages used for the StreamDirectory.
     pub view: SourceView,
+    /// Linear mapping of the stream block map, the PFN's of the StreamDirectory.
+    pub block_map: SourceView,
 }
 
 impl StreamDirectory {
     /// Lift entire stream directory table and all streams as well.
-    pub fn new(bytes: &[u8], view: SourceView, header: &MsfBigHeader<'_>) -> Result<Self, Error> {
+    pub fn new(
+        bytes: &[u8],
+        view: SourceView,
+        block_map: SourceView,
+        header: &MsfBigHeader<'_>,
+    ) -> Result<Self, Error> {
+        let mut dir = Self::parse(view, block_map, header)?;
+        dir.check_num_pages(bytes.len() as u64 / header.get_page_size() as u64)?;
+        // Parse the streams out of the PDB file now.
+        for (index, stream) in dir.streams.iter_mut().enumerate() {
+            if stream.original_stream_size != INVALID_STREAM_SIZE {
+                stream.view = SourceView::with_size(
+                    bytes,
+                    stream.view.pages.clone(),
+                    stream.original_stream_size as usize,
+                )
+                .map_err(|e| stream.truncated(index, e))?;
+            }
+        }
+        Ok(dir)
+    }
+    /// Lift the stream directory table only. The pages of every stream are
+    /// known but none of their bytes are read.
+    pub fn parse(
+        view: SourceView,
+        block_map: SourceView,
+        header: &MsfBigHeader<'_>,
+    ) -> Result<Self, Error> {
         let buff = view.as_slice();
         let mut offset = 0;
         // Read the number of streams.
-        let num_streams = buff.gread::<u32>(&mut offset)?;
+        let num_streams = read_u32(buff, &mut offset)?;
         let mut streams = Vec::<Stream>::new();
         // Read all of the sizes for each stream.
         for _ in 0..num_streams {
             streams.push(Stream {
-                original_stream_size: buff.gread::<u32>(&mut offset)?,
+                original_stream_size: read_u32(buff, &mut offset)?,
                 ..Default::default()
             });
         }
@@ -54,73 +146,195 @@ impl StreamDirectory {
                 let num_pages = header.pages_needed_to_store(stream.original_stream_size);
                 let mut pages = PageList::new(header.get_page_size());
                 for _ in 0..num_pages {
-                    pages.push(buff.gread::<u32>(&mut offset)?);
+                    pages.push(read_u32(buff, &mut offset)?);
                 }
-                // Parse the stream out of the PDB file now.
-                stream.view =
-                    SourceView::with_size(bytes, pages, stream.original_stream_size as usize)
-                        .ok_or_else(|| {
-                            Error::Custom("Failed to create view for streams!".to_string())
-                        })?;
+                stream.view.pages = pages;
             }
         }
-        Ok(Self { view, streams })
+        Ok(Self {
+            view,
+            block_map,
+            streams,
+        })
+    }
+    /// Streams of a valid file never share pages, so together they cannot have
+    /// more pages than the file. This stops hostile files from using the same
+    /// pages over and over to make us allocate a lot of memory.
+    pub fn check_num_pages(&self, num_pages: u64) -> Result<(), Error> {
+        let pages = self
+            .streams
+            .iter()
+            .map(|stream| stream.view.pages.pfns.len() as u64)
+            .sum::<u64>();
+        if pages > num_pages {
+            return Err(Error::TooManyPages { pages, num_pages });
+        }
+        Ok(())
+    }
+    /// Get a stream by its index.
+    pub fn stream(&self, index: usize) -> Result<&Stream, Error> {
+        self.streams.get(index).ok_or(Error::MissingStream(index))
+    }
+    /// Get a mutable stream by its index.
+    pub fn stream_mut(&mut self, index: usize) -> Result<&mut Stream, Error> {
+        self.streams
+            .get_mut(index)
+            .ok_or(Error::MissingStream(index))
+    }
+    /// Add a new stream at the end of the directory and return its index.
+    /// Pages are allocated for it on the next flush.
+    pub fn add_stream(&mut self, bytes: Vec<u8>) -> usize {
+        self.streams.push(Stream {
+            original_stream_size: Default::default(),
+            view: SourceView {
+                bytes,
+                pages: PageList::new(self.view.pages.page_size),
+            },
+        });
+        self.streams.len() - 1
+    }
+    /// Replace the contents of a stream. Its pages are reused on the next flush,
+    /// pages it no longer needs are freed and new ones are allocated if it grew.
+    pub fn replace_stream(&mut self, index: usize, bytes: Vec<u8>) -> Result<(), Error> {
+        self.stream_mut(index)?.view.bytes = bytes;
+        Ok(())
+    }
+    /// Turn a stream into a nil stream, its pages are freed on the next flush.
+    /// The indices of the other streams stay the same.
+    pub fn remove_stream(&mut self, index: usize) -> Result<(), Error> {
+        let stream = self.stream_mut(index)?;
+        stream.original_stream_size = INVALID_STREAM_SIZE;
+        stream.view.bytes.clear();
+        stream.view.pages.pfns.clear();
+        Ok(())
     }
-    /// Flush directory back into the file.
+    /// Forget which pages every stream, the directory and the block map live on.
+    /// The next flush lays all of them out from scratch.
+    pub fn clear_pages(&mut self, page_size: u32) {
+        for stream in self.streams.iter_mut() {
+            stream.view.pages = PageList::new(page_size);
+        }
+        self.view.pages = PageList::new(page_size);
+        self.block_map.pages = PageList::new(page_size);
+    }
+    /// Flush directory back into the file. Pages of streams that shrunk or
+    /// were emptied are released and reused by streams that grew.
     #[inline(always)]
     pub fn flush(
         &mut self,
         buff: &mut Vec<u8>,
         header: &mut MsfBigHeaderMut<'_>,
+        allocator: &mut PageAllocator,
     ) -> Result<(), Error> {
         // Compute the size of the StreamDirectory
         // NumberOfStreams is 4 bytes.
-        let mut stream_directory_size = 4u32;
+        let mut stream_directory_size = 4u64;
         // Each stream needs 4 bytes for its len.
-        stream_directory_size += self.streams.len() as u32 * 4;
+        stream_directory_size += self.streams.len() as u64 * 4;
         // Compute how many PFN's there are for all streams.
-        for stream in self.streams.iter_mut() {
-            // Flush stream bytes back now.
-            stream.view.flush(buff, header);
+        for stream in self.streams.iter() {
             // DWORD for each pfn.
-            stream_directory_size += stream.view.pages.pfns.len() as u32 * 4;
+            stream_directory_size +=
+                allocator.pages_needed_to_store(stream.view.bytes.len()) as u64 * 4;
+        }
+        let stream_directory_size =
+            u32::try_from(stream_directory_size).map_err(|_| Error::DirectoryTooLarge {
+                size: stream_directory_size,
+            })?;
+        // Each stream block map page holds the PFN's of "page_size / 4" directory pages.
+        let directory_pages = allocator.pages_needed_to_store(stream_directory_size as usize);
+        let block_map_pages = allocator.pages_needed_to_store(directory_pages * 4);
+        if block_map_pages > header.max_block_map_pages() as usize {
+            return Err(Error::DirectoryTooLarge {
+                size: stream_directory_size as u64,
+            });
         }
-        // Update the size of the stream directory.
-        header.set_stream_dir_size(stream_directory_size);
-        // Write the stream directory into the view now.
-        let mut offset = 0;
         // Resize the mapping of the StreamDirectory.
         self.view.bytes.resize(stream_directory_size as usize, 0);
-        // Write the number of streams (NumStreams)
-        self.view
+        // Whole pages of the block map are written so stale PFN's get zeroed.
+        let old_block_map = std::mem::take(&mut self.block_map.bytes);
+        let old_block_map_pages = self.block_map.pages.pfns.clone();
+        self.block_map
             .bytes
-            .gwrite::<u32>(self.streams.len() as u32, &mut offset)?;
-        // Write each streams size now.
-        for stream in self.streams.iter() {
-            if stream.view.bytes.is_empty() {
-                self.view.bytes.gwrite::<u32>(u32::MAX, &mut offset)?;
-            } else {
-                self.view
-                    .bytes
-                    .gwrite::<u32>(stream.view.bytes.len() as u32, &mut offset)?;
-            }
+            .resize(block_map_pages * allocator.page_size() as usize, 0);
+        // Hostile files can put views on the header or FPM pages, which are
+        // about to be overwritten, or put several views on the same page. Only
+        // the first view keeps such a page, the others move to new pages.
+        let page_size = allocator.page_size();
+        let mut claimed = HashSet::<PageNumber>::new();
+        for view in self
+            .streams
+            .iter_mut()
+            .map(|stream| &mut stream.view)
+            .chain([&mut self.view, &mut self.block_map])
+        {
+            view.pages
+                .pfns
+                .retain(|pfn| !is_reserved_page(*pfn, page_size) && claimed.insert(*pfn));
         }
-        // Write each streams pfn now.
-        for stream in self.streams.iter() {
-            for pfn in stream.view.pages.pfns.iter() {
-                self.view.bytes.gwrite::<u32>(*pfn, &mut offset)?;
-            }
+        // Reserve every page still in use before any new pages are handed out.
+        self.block_map.reserve(allocator);
+        self.view.reserve(allocator);
+        for stream in self.streams.iter_mut() {
+            stream.view.reserve(allocator);
+        }
+        // Pages outside of the file can not be written to.
+        if let Some(pfn) = self
+            .streams
+            .iter()
+            .map(|stream| &stream.view)
+            .chain([&self.view, &self.block_map])
+            .flat_map(|view| view.pages.pfns.iter())
+            .find(|pfn| **pfn >= allocator.num_pages())
+        {
+            return Err(Error::BadPageNumber {
+                pfn: *pfn,
+                num_pages: allocator.num_pages() as u64,
+            });
+        }
+        // Flush stream bytes back now.
+        for stream in self.streams.iter_mut() {
+            stream.view.flush(buff, allocator);
         }
+        // Update the size of the stream directory.
+        header.set_stream_dir_size(stream_directory_size);
+        // Write the stream directory into the view now.
+        write_directory(
+            &mut self.view.bytes,
+            self.streams.iter().map(|stream| {
+                let size = if stream.is_nil() {
+                    INVALID_STREAM_SIZE
+                } else {
+                    stream.view.bytes.len() as u32
+                };
+                (size, stream.view.pages.pfns.as_slice())
+            }),
+        )?;
         // Flush the stream directory back to the file.
-        self.view.flush(buff, header);
-        // Finally we need to update the StreamDirectoryMap page.
-        let stream_block_map = &mut buff[header.stream_block_map()..];
-        // Zero the map page for debug purposes.
-        stream_block_map[0..header.get_page_size() as usize].fill(0);
-        // Write the PFN's used by the StreamDirectory
+        self.view.flush(buff, allocator);
+        // Write the PFN's used by the StreamDirectory into the block map.
         let mut offset = 0;
         for pfn in self.view.pages.pfns.iter() {
-            stream_block_map.gwrite::<u32>(*pfn, &mut offset)?;
+            self.block_map.bytes.gwrite::<u32>(*pfn, &mut offset)?;
+        }
+        // The block map and the array in the header page are left alone when
+        // the directory did not move, so a file without edits stays the same.
+        let unchanged = old_block_map_pages == self.block_map.pages.pfns
+            && old_block_map.get(..offset) == self.block_map.bytes.get(..offset);
+        if !unchanged {
+            self.block_map.flush(buff, allocator);
+            // Finally we need to update the array of block map pages in the header page.
+            let block_map_array = buff
+                .get_mut(BLOCK_MAP_OFFSET..header.get_page_size() as usize)
+                .ok_or(Error::TruncatedHeader("MSF"))?;
+            block_map_array.fill(0);
+            let mut offset = 0;
+            for pfn in self.block_map.pages.pfns.iter() {
+                block_map_array.gwrite::<u32>(*pfn, &mut offset)?;
+            }
+        }
+        if let Some(pfn) = self.block_map.pages.pfns.first() {
+            header.set_stream_block_map(*pfn);
         }
         Ok(())
     }
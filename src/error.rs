@@ -0,0 +1,160 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::fmt;
+
+use crate::msf::PageNumber;
+
+/// Everything that can go wrong while reading or writing a PDB. "is_unsupported"
+/// tells input this library does not handle apart from corrupted input.
+#[derive(Debug)]
+pub enum Error {
+    /// The file does not start with the magic of any supported container.
+    BadMagic,
+    /// A header is larger than the bytes it is read from.
+    TruncatedHeader(&'static str),
+    /// The page size is not one MSF files can use.
+    BadPageSize(u32),
+    /// A page number points past the end of the file.
+    BadPageNumber { pfn: PageNumber, num_pages: u64 },
+    /// Views of the file need "pages" pages but the file only has "num_pages",
+    /// so some pages are shared which never happens in a valid file.
+    TooManyPages { pages: u64, num_pages: u64 },
+    /// Streams of "size" bytes were read from only "limit" bytes, so some bytes
+    /// are shared which never happens in a valid file.
+    TooManyBytes { size: u64, limit: u64 },
+    /// The FPM page in the header is neither 1 nor 2.
+    BadFreePageMap(u32),
+    /// The stream directory of "size" bytes does not fit in its container.
+    DirectoryTooLarge { size: u64 },
+    /// The stream directory ends before "offset".
+    TruncatedDirectory { offset: usize },
+    /// A stream ends before "offset".
+    TruncatedStream { index: usize, offset: usize },
+    /// The stream does not exist.
+    MissingStream(usize),
+    /// The DBI stream does not point at this optional debug stream.
+    MissingDebugStream(&'static str),
+    /// A stream index does not fit the 16 bits other streams refer to it with.
+    StreamIndexTooLarge(usize),
+    /// The named stream map has no stream with this name.
+    MissingNamedStream(String),
+    /// The PDB has no DBI stream.
+    MissingDbiStream,
+    /// A substream of the DBI stream does not fit in the DBI stream.
+    SubstreamOutOfBounds {
+        substream: &'static str,
+        offset: usize,
+        size: usize,
+    },
+    /// A structure does not start with its signature.
+    BadSignature {
+        structure: &'static str,
+        signature: u32,
+    },
+    /// A serialized hash table has more or fewer entries than "size", or
+    /// entries in buckets past "capacity".
+    BadHashTable { size: u32, capacity: u32 },
+    /// A read past the end of a file.
+    OutOfBounds { offset: u64, size: u64 },
+    /// Data that had to be decompressed is corrupted.
+    Decompress(String),
+    /// Valid input which this library does not handle, like a newer version.
+    Unsupported(String),
+    /// Reading or writing a file failed.
+    Io(std::io::Error),
+    /// Reading or writing a structure failed.
+    Scroll(scroll::Error),
+}
+
+impl Error {
+    /// Is the input valid but not supported, rather than corrupted?
+    pub fn is_unsupported(&self) -> bool {
+        matches!(self, Self::BadMagic | Self::Unsupported(_))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "Unknown PDB container!"),
+            Self::TruncatedHeader(header) => write!(f, "Failed to parse {header} header!"),
+            Self::BadPageSize(page_size) => write!(f, "Invalid page size {page_size:#x}!"),
+            Self::BadPageNumber { pfn, num_pages } => {
+                write!(f, "Page {pfn:#x} is past the end of {num_pages:#x} pages!")
+            }
+            Self::TooManyPages { pages, num_pages } => {
+                write!(
+                    f,
+                    "{pages:#x} pages are used but there are only {num_pages:#x}!"
+                )
+            }
+            Self::TooManyBytes { size, limit } => {
+                write!(f, "{size:#x} bytes are used but there are only {limit:#x}!")
+            }
+            Self::BadFreePageMap(fpm) => write!(f, "Invalid FPM page {fpm}!"),
+            Self::DirectoryTooLarge { size } => {
+                write!(f, "Stream directory of {size:#x} bytes is too large!")
+            }
+            Self::TruncatedDirectory { offset } => {
+                write!(f, "Stream directory is truncated at {offset:#x}!")
+            }
+            Self::TruncatedStream { index, offset } => {
+                write!(f, "Stream {index} is truncated at {offset:#x}!")
+            }
+            Self::MissingStream(index) => write!(f, "Stream {index} does not exist!"),
+            Self::MissingDebugStream(kind) => {
+                write!(f, "The {kind} debug stream does not exist!")
+            }
+            Self::StreamIndexTooLarge(index) => {
+                write!(f, "Stream {index} can not be referenced by a 16 bit index!")
+            }
+            Self::MissingNamedStream(name) => write!(f, "Stream {name} does not exist!"),
+            Self::MissingDbiStream => write!(f, "The DBI stream does not exist!"),
+            Self::SubstreamOutOfBounds {
+                substream,
+                offset,
+                size,
+            } => write!(
+                f,
+                "Substream {substream} at {offset:#x}+{size:#x} is out of bounds!"
+            ),
+            Self::BadSignature {
+                structure,
+                signature,
+            } => write!(f, "Invalid {structure} signature {signature:#x}!"),
+            Self::BadHashTable { size, capacity } => write!(
+                f,
+                "Hash table with {size} entries in {capacity} buckets is invalid!"
+            ),
+            Self::OutOfBounds { offset, size } => {
+                write!(f, "Read at {offset:#x}+{size:#x} is out of bounds!")
+            }
+            Self::Decompress(e) => write!(f, "Failed to decompress: {e}"),
+            Self::Unsupported(e) => write!(f, "Unsupported: {e}"),
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Scroll(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Scroll(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<scroll::Error> for Error {
+    fn from(e: scroll::Error) -> Self {
+        Self::Scroll(e)
+    }
+}
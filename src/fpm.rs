@@ -0,0 +1,247 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::io::{Seek, SeekFrom, Write};
+
+use crate::{
+    error::Error,
+    msf::{page_offset, MsfBigHeader, MsfBigHeaderMut, PageNumber},
+};
+
+/// Is this page reserved by the MSF itself? Page 0 holds the MSF header and
+/// every interval of "page_size" pages begins with the header/data page followed
+/// by the two FPM pages. https://llvm.org/docs/PDB/MsfFile.html#the-free-block-map
+#[inline(always)]
+pub fn is_reserved_page(pfn: PageNumber, page_size: u32) -> bool {
+    pfn == 0 || is_fpm_page(pfn, page_size)
+}
+
+/// Is this page one of the two FPM pages of its interval?
+#[inline(always)]
+pub fn is_fpm_page(pfn: PageNumber, page_size: u32) -> bool {
+    matches!(pfn % page_size, 1 | 2)
+}
+
+/// How many pages must the file have so that both FPM pages of the
+/// last interval exist? Any new interval must come with its FPM pages.
+#[inline(always)]
+pub fn pad_num_pages(num_pages: u32, page_size: u32) -> u32 {
+    match num_pages % page_size {
+        1 => num_pages + 2,
+        2 => num_pages + 1,
+        _ => num_pages,
+    }
+}
+
+/// Bitmap of every page in the MSF file. A set bit means the page is free.
+#[derive(Debug, Default, Clone)]
+pub struct FreePageMap {
+    /// Page size of the MSF file, this is also the interval of the FPM pages.
+    pub page_size: u32,
+    /// Number of pages in the MSF file.
+    pub num_pages: u32,
+    /// One bit per page, a set bit means the page is free.
+    pub bits: Vec<u8>,
+}
+
+impl FreePageMap {
+    /// Create a new FPM where every page is free except the reserved ones.
+    pub fn new(num_pages: u32, page_size: u32) -> Self {
+        let mut fpm = Self {
+            page_size,
+            num_pages,
+            bits: vec![0xFF; num_pages.div_ceil(8) as usize],
+        };
+        for pfn in 0..num_pages {
+            if is_reserved_page(pfn, page_size) {
+                fpm.mark_used(pfn);
+            }
+        }
+        fpm
+    }
+    /// Is the page free?
+    #[inline(always)]
+    pub fn is_free(&self, pfn: PageNumber) -> bool {
+        pfn < self.num_pages && self.bits[(pfn / 8) as usize] & (1 << (pfn % 8)) != 0
+    }
+    /// Mark a page as used. Pages outside of the file are ignored.
+    #[inline(always)]
+    pub fn mark_used(&mut self, pfn: PageNumber) {
+        if pfn < self.num_pages {
+            self.bits[(pfn / 8) as usize] &= !(1 << (pfn % 8));
+        }
+    }
+    /// Mark a page as free. Pages outside of the file are ignored.
+    #[inline(always)]
+    pub fn mark_free(&mut self, pfn: PageNumber) {
+        if pfn < self.num_pages {
+            self.bits[(pfn / 8) as usize] |= 1 << (pfn % 8);
+        }
+    }
+    /// Read the FPM selected by the header from every interval. Intervals whose
+    /// FPM page is past the end of the buffer read as free, pages the header
+    /// claims past the end of the buffer do not exist. The page size in the
+    /// header must be valid.
+    pub fn read(buff: &[u8], header: &MsfBigHeader<'_>) -> Self {
+        let page_size = header.get_page_size();
+        let num_pages = std::cmp::min(
+            header.get_num_pages() as u64,
+            buff.len() as u64 / page_size.max(1) as u64,
+        ) as u32;
+        let mut fpm = Self {
+            page_size,
+            num_pages,
+            bits: vec![0xFF; num_pages.div_ceil(8) as usize],
+        };
+        let fpm_page = header.get_free_page_map();
+        for (interval, bits) in fpm.bits.chunks_mut(page_size as usize).enumerate() {
+            let pfn = interval as u64 * page_size as u64 + fpm_page as u64;
+            let page_start = pfn * page_size as u64;
+            if let Some(page) = usize::try_from(page_start)
+                .ok()
+                .and_then(|page_start| buff.get(page_start..page_start + bits.len()))
+            {
+                bits.copy_from_slice(page);
+            }
+        }
+        fpm
+    }
+    /// Write the FPM into every interval of the FPM selected by the header.
+    /// Bits past the end of the file are left free like link.exe does.
+    pub fn flush(&self, buff: &mut [u8], header: &MsfBigHeaderMut<'_>) {
+        let page_size = self.page_size as usize;
+        let fpm_page = header.get_free_page_map();
+        let num_intervals = self.num_pages.div_ceil(self.page_size);
+        for interval in 0..num_intervals {
+            let pfn = interval * self.page_size + fpm_page;
+            let page_start = page_offset(pfn, self.page_size) as usize;
+            self.fill_page(interval, &mut buff[page_start..page_start + page_size]);
+        }
+    }
+    /// Same as "flush" but the FPM pages are written to "out".
+    pub fn write_to<W: Write + Seek>(
+        &self,
+        out: &mut W,
+        header: &MsfBigHeaderMut<'_>,
+    ) -> Result<(), Error> {
+        let fpm_page = header.get_free_page_map();
+        let num_intervals = self.num_pages.div_ceil(self.page_size);
+        let mut page = vec![0u8; self.page_size as usize];
+        for interval in 0..num_intervals {
+            let pfn = interval * self.page_size + fpm_page;
+            self.fill_page(interval, &mut page);
+            out.seek(SeekFrom::Start(page_offset(pfn, self.page_size)))?;
+            out.write_all(&page)?;
+        }
+        Ok(())
+    }
+    /// Fill the FPM page of an interval, each one holds "page_size" bytes of the bitmap.
+    fn fill_page(&self, interval: u32, page: &mut [u8]) {
+        let page_size = self.page_size as usize;
+        page.fill(0xFF);
+        let bits_start = std::cmp::min(interval as usize * page_size, self.bits.len());
+        let bits_end = std::cmp::min(bits_start + page_size, self.bits.len());
+        page[..bits_end - bits_start].copy_from_slice(&self.bits[bits_start..bits_end]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_reserved_page, pad_num_pages, FreePageMap};
+    use crate::msf::{page_offset, MsfBigHeaderMut};
+
+    /// Every interval starts with a data page followed by two FPM pages.
+    #[test]
+    fn reserved_pages() {
+        for page_size in [512u32, 1024, 2048, 4096] {
+            assert!(is_reserved_page(0, page_size));
+            assert!(is_reserved_page(1, page_size));
+            assert!(is_reserved_page(2, page_size));
+            assert!(!is_reserved_page(3, page_size));
+            assert!(!is_reserved_page(page_size, page_size));
+            assert!(is_reserved_page(page_size + 1, page_size));
+            assert!(is_reserved_page(page_size + 2, page_size));
+            assert_eq!(pad_num_pages(page_size + 1, page_size), page_size + 3);
+            assert_eq!(pad_num_pages(page_size + 2, page_size), page_size + 3);
+            assert_eq!(pad_num_pages(page_size + 3, page_size), page_size + 3);
+        }
+    }
+
+    /// Make sure the bitmap lands in the FPM page of every interval.
+    #[test]
+    fn flush_fpm_intervals() {
+        for page_size in [512u32, 1024, 2048, 4096] {
+            let num_pages = page_size * 9 + 3;
+            let mut buff = vec![0u8; (num_pages * page_size) as usize];
+            let mut header_bytes = vec![0u8; 0x1000];
+            let mut header = MsfBigHeaderMut::new(&mut header_bytes).unwrap();
+            header.set_page_size(page_size);
+            header.set_free_page_map(2);
+            header.set_num_pages(num_pages);
+            let mut fpm = FreePageMap::new(num_pages, page_size);
+            fpm.mark_used(3);
+            fpm.mark_used(num_pages - 1);
+            fpm.flush(&mut buff, &header);
+            let fpm_page = |interval: u32| {
+                let start = ((interval * page_size + 2) * page_size) as usize;
+                &buff[start..start + page_size as usize]
+            };
+            // Pages 0-3 are used, the rest of the first byte is free.
+            assert_eq!(fpm_page(0)[0], 0xF0);
+            // The last page lives in the second FPM page of the bitmap.
+            let last = num_pages - 1;
+            let byte = (last / 8) as usize;
+            assert_eq!(byte / page_size as usize, 1);
+            assert_eq!(
+                fpm_page(1)[byte % page_size as usize] & (1 << (last % 8)),
+                0
+            );
+            // Page "page_size + 1" is an FPM page and must be used.
+            let pfn = page_size + 1;
+            assert_eq!(fpm_page(0)[(pfn / 8) as usize] & (1 << (pfn % 8)), 0);
+            // Everything past the end of the file is free.
+            assert_eq!(fpm_page(1)[byte % page_size as usize + 1], 0xFF);
+            assert!(fpm_page(9).iter().all(|&e| e == 0xFF));
+            // The other FPM is left alone.
+            let start = ((page_size + 1) * page_size) as usize;
+            assert!(buff[start..start + page_size as usize]
+                .iter()
+                .all(|&e| e == 0));
+        }
+    }
+
+    /// Big pages have big intervals, the FPM must still land in every one of them.
+    #[test]
+    fn flush_fpm_big_pages() {
+        for page_size in [8192u32, 16384, 32768] {
+            let num_pages = if page_size == 8192 { page_size + 3 } else { 16 };
+            let mut buff = vec![0u8; page_offset(num_pages, page_size) as usize];
+            let mut header_bytes = vec![0u8; 0x1000];
+            let mut header = MsfBigHeaderMut::new(&mut header_bytes).unwrap();
+            header.set_page_size(page_size);
+            header.set_free_page_map(1);
+            header.set_num_pages(num_pages);
+            let mut fpm = FreePageMap::new(num_pages, page_size);
+            fpm.mark_used(3);
+            fpm.flush(&mut buff, &header);
+            let start = page_size as usize;
+            assert_eq!(buff[start], 0xF0);
+            assert!(buff[start + 1..start + (num_pages / 8) as usize]
+                .iter()
+                .all(|&e| e == 0xFF || e == 0xF9));
+            if num_pages > page_size {
+                // Second interval FPM page is all free, it holds no pages of this file.
+                let start = page_offset(page_size + 1, page_size) as usize;
+                assert!(buff[start..start + page_size as usize]
+                    .iter()
+                    .all(|&e| e == 0xFF));
+            }
+        }
+    }
+
+    /// Offsets of pages past 4 GiB must not wrap.
+    #[test]
+    fn page_offset_over_4gib() {
+        assert_eq!(page_offset(0x20000, 0x8000), 0x1_0000_0000);
+        assert_eq!(page_offset(u32::MAX, 0x8000), 0x7FFF_FFFF_8000);
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+// Hash functions of MSPDB, every hash table in a PDB depends on them.
+// https://github.com/microsoft/microsoft-pdb/blob/master/PDB/include/misc.h
+// https://llvm.org/docs/PDB/HashTable.html
+
+/// Reflected polynomial of CRC32.
+const CRC32_POLYNOMIAL: u32 = 0xEDB88320;
+
+/// Lookup table of CRC32, one entry for every byte.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32_POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// LHashPbCb, used by the named stream map (truncated to 16 bits), version 1
+/// of the "/names" string table, the GSI hash tables and the TPI hashes of
+/// UDT names. Names hash the same regardless of their ASCII case.
+pub fn hash_string_v1(bytes: &[u8]) -> u32 {
+    let mut result = 0u32;
+    let mut chunks = bytes.chunks_exact(4);
+    for chunk in chunks.by_ref() {
+        result ^= u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    let mut remainder = chunks.remainder();
+    if remainder.len() >= 2 {
+        result ^= u16::from_le_bytes([remainder[0], remainder[1]]) as u32;
+        remainder = &remainder[2..];
+    }
+    if let Some(byte) = remainder.first() {
+        result ^= *byte as u32;
+    }
+    result |= 0x20202020;
+    result ^= result >> 11;
+    result ^ (result >> 16)
+}
+
+/// LHashPbCbV2, used by version 2 of the "/names" string table.
+pub fn hash_string_v2(bytes: &[u8]) -> u32 {
+    let mut hash = 0xB170A1BFu32;
+    let mut chunks = bytes.chunks_exact(4);
+    let mut mix = |item: u32| {
+        hash = hash.wrapping_add(item);
+        hash = hash.wrapping_add(hash << 10);
+        hash ^= hash >> 6;
+    };
+    for chunk in chunks.by_ref() {
+        mix(u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+    }
+    for byte in chunks.remainder() {
+        mix(*byte as u32);
+    }
+    hash.wrapping_mul(1664525).wrapping_add(1013904223)
+}
+
+/// Update a CRC32 without inverting it before or after, MSPDB's "SigForPbCb".
+pub fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(crc, |crc, byte| {
+        (crc >> 8) ^ CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize]
+    })
+}
+
+/// The standard CRC32 of zlib and PKZIP.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !crc32_update(!0, bytes)
+}
+
+/// hashBufferV8, a CRC32 starting at 0. Used by the TPI and IPI hash streams
+/// for whole type records, including their length prefix.
+pub fn hash_buffer_v8(bytes: &[u8]) -> u32 {
+    crc32_update(0, bytes)
+}
@@ -1,9 +1,27 @@
 // Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
 
+pub mod allocator;
+pub mod builder;
 pub mod dbi;
 pub mod directory;
+pub mod error;
+pub mod fpm;
+pub mod hash;
+pub mod modinfo;
 pub mod msf;
+pub mod msfz;
+pub mod namedstreammap;
 pub mod omap;
 pub mod overlays;
+pub mod paged;
 pub mod pagelist;
+pub mod pdbinfo;
+#[cfg(feature = "pdb")]
+pub mod pdbsource;
+pub mod sectionmap;
+pub mod smallmsf;
+pub mod source;
+pub mod stringtable;
+pub mod tpi;
+pub mod verify;
 pub mod view;
@@ -0,0 +1,249 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::borrow::Cow;
+
+use crate::{directory::INVALID_STREAM_INDEX, error::Error};
+use scroll::{Pread, Pwrite};
+
+/// Size of a section contribution, as written by VC 6.0 and newer.
+pub const SECTION_CONTRIBUTION_SIZE: usize = 0x1C;
+/// Size of a module info entry without its names.
+pub const MODULE_INFO_HEADER_SIZE: usize = 0x40;
+
+/// A range of a section that was contributed by one module.
+/// https://llvm.org/docs/PDB/DbiStream.html#section-contribution-substream
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SectionContribution {
+    /// One based index of the section, 0xFFFF for none.
+    pub section: u16,
+    /// Padding after "section", kept as it was read.
+    pub padding1: u16,
+    pub offset: u32,
+    pub size: u32,
+    pub characteristics: u32,
+    /// Index of the module in the module info substream.
+    pub module_index: u16,
+    /// Padding after "module_index", kept as it was read.
+    pub padding2: u16,
+    pub data_crc: u32,
+    pub reloc_crc: u32,
+}
+
+impl SectionContribution {
+    /// Parse a section contribution at "offset".
+    pub fn read(bytes: &[u8], offset: &mut usize) -> Result<Self, Error> {
+        let start = *offset;
+        let mut read = || -> Result<Self, scroll::Error> {
+            let section = bytes.gread::<u16>(offset)?;
+            let padding1 = bytes.gread::<u16>(offset)?;
+            let contribution_offset = bytes.gread::<u32>(offset)?;
+            let size = bytes.gread::<u32>(offset)?;
+            let characteristics = bytes.gread::<u32>(offset)?;
+            let module_index = bytes.gread::<u16>(offset)?;
+            let padding2 = bytes.gread::<u16>(offset)?;
+            Ok(Self {
+                section,
+                padding1,
+                offset: contribution_offset,
+                size,
+                characteristics,
+                module_index,
+                padding2,
+                data_crc: bytes.gread::<u32>(offset)?,
+                reloc_crc: bytes.gread::<u32>(offset)?,
+            })
+        };
+        read().map_err(|_| Error::OutOfBounds {
+            offset: start as u64,
+            size: SECTION_CONTRIBUTION_SIZE as u64,
+        })
+    }
+    /// Append the section contribution.
+    pub fn write(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        let mut offset = bytes.len();
+        bytes.resize(offset + SECTION_CONTRIBUTION_SIZE, 0);
+        bytes.gwrite::<u16>(self.section, &mut offset)?;
+        bytes.gwrite::<u16>(self.padding1, &mut offset)?;
+        bytes.gwrite::<u32>(self.offset, &mut offset)?;
+        bytes.gwrite::<u32>(self.size, &mut offset)?;
+        bytes.gwrite::<u32>(self.characteristics, &mut offset)?;
+        bytes.gwrite::<u16>(self.module_index, &mut offset)?;
+        bytes.gwrite::<u16>(self.padding2, &mut offset)?;
+        bytes.gwrite::<u32>(self.data_crc, &mut offset)?;
+        bytes.gwrite::<u32>(self.reloc_crc, &mut offset)?;
+        Ok(())
+    }
+}
+
+/// A module (object file) of the module info substream of the DBI stream.
+/// https://llvm.org/docs/PDB/DbiStream.html#module-info-substream
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ModuleInfo {
+    /// A pointer to the opened module in MSPDB, unused.
+    pub unused1: u32,
+    /// The first section contribution of the module.
+    pub section_contribution: SectionContribution,
+    /// Bit 0 is set if the module was written, bit 1 if it has EC info and
+    /// bits 8 to 15 are the index of its type server.
+    pub flags: u16,
+    /// Stream of the module's symbols and line info, INVALID_STREAM_INDEX for
+    /// modules without one.
+    pub symbol_stream_index: u16,
+    /// Bytes of CodeView symbols at the start of the symbol stream.
+    pub sym_byte_size: u32,
+    /// Bytes of C11 line info after the symbols.
+    pub c11_byte_size: u32,
+    /// Bytes of C13 line info after the C11 line info.
+    pub c13_byte_size: u32,
+    pub source_file_count: u16,
+    /// Padding after "source_file_count", kept as it was read.
+    pub padding: u16,
+    /// Offsets of the file names in MSPDB, unused.
+    pub unused2: u32,
+    /// Offset of the source file name in the EC substream.
+    pub source_file_name_index: u32,
+    /// Offset of the PDB file path in the EC substream.
+    pub pdb_file_path_name_index: u32,
+    /// An object file path or "Import:<dll name>", in the ANSI code page of
+    /// whoever wrote the PDB.
+    pub module_name: Vec<u8>,
+    /// The same as "module_name", or the library the object file came from.
+    pub object_name: Vec<u8>,
+}
+
+impl ModuleInfo {
+    /// Parse the module at "offset", which is moved to the next module.
+    pub fn read(bytes: &[u8], offset: &mut usize) -> Result<Self, Error> {
+        let start = *offset;
+        let truncated = || Error::OutOfBounds {
+            offset: start as u64,
+            size: MODULE_INFO_HEADER_SIZE as u64,
+        };
+        if bytes.len().saturating_sub(start) < MODULE_INFO_HEADER_SIZE {
+            return Err(truncated());
+        }
+        let unused1 = bytes.gread::<u32>(offset)?;
+        let section_contribution = SectionContribution::read(bytes, offset)?;
+        let flags = bytes.gread::<u16>(offset)?;
+        let symbol_stream_index = bytes.gread::<u16>(offset)?;
+        let sym_byte_size = bytes.gread::<u32>(offset)?;
+        let c11_byte_size = bytes.gread::<u32>(offset)?;
+        let c13_byte_size = bytes.gread::<u32>(offset)?;
+        let source_file_count = bytes.gread::<u16>(offset)?;
+        let padding = bytes.gread::<u16>(offset)?;
+        let unused2 = bytes.gread::<u32>(offset)?;
+        let source_file_name_index = bytes.gread::<u32>(offset)?;
+        let pdb_file_path_name_index = bytes.gread::<u32>(offset)?;
+        let read_name = |offset: &mut usize| -> Result<Vec<u8>, Error> {
+            let name = &bytes[*offset..];
+            let len = name
+                .iter()
+                .position(|byte| *byte == 0)
+                .ok_or(Error::OutOfBounds {
+                    offset: *offset as u64,
+                    size: name.len() as u64 + 1,
+                })?;
+            let name = name[..len].to_vec();
+            *offset += len + 1;
+            Ok(name)
+        };
+        let module_name = read_name(offset)?;
+        let object_name = read_name(offset)?;
+        *offset = offset.next_multiple_of(4);
+        Ok(Self {
+            unused1,
+            section_contribution,
+            flags,
+            symbol_stream_index,
+            sym_byte_size,
+            c11_byte_size,
+            c13_byte_size,
+            source_file_count,
+            padding,
+            unused2,
+            source_file_name_index,
+            pdb_file_path_name_index,
+            module_name,
+            object_name,
+        })
+    }
+    /// Append the module, padded to 4 bytes.
+    pub fn write(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        let start = bytes.len();
+        bytes.extend_from_slice(&self.unused1.to_le_bytes());
+        self.section_contribution.write(bytes)?;
+        let mut offset = bytes.len();
+        bytes.resize(start + MODULE_INFO_HEADER_SIZE, 0);
+        bytes.gwrite::<u16>(self.flags, &mut offset)?;
+        bytes.gwrite::<u16>(self.symbol_stream_index, &mut offset)?;
+        bytes.gwrite::<u32>(self.sym_byte_size, &mut offset)?;
+        bytes.gwrite::<u32>(self.c11_byte_size, &mut offset)?;
+        bytes.gwrite::<u32>(self.c13_byte_size, &mut offset)?;
+        bytes.gwrite::<u16>(self.source_file_count, &mut offset)?;
+        bytes.gwrite::<u16>(self.padding, &mut offset)?;
+        bytes.gwrite::<u32>(self.unused2, &mut offset)?;
+        bytes.gwrite::<u32>(self.source_file_name_index, &mut offset)?;
+        bytes.gwrite::<u32>(self.pdb_file_path_name_index, &mut offset)?;
+        for name in [&self.module_name, &self.object_name] {
+            bytes.extend_from_slice(name);
+            bytes.push(0);
+        }
+        bytes.resize(bytes.len().next_multiple_of(4), 0);
+        Ok(())
+    }
+    /// The module name, bytes which are not UTF-8 are replaced.
+    pub fn module_name_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.module_name)
+    }
+    /// The object name, bytes which are not UTF-8 are replaced.
+    pub fn object_name_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.object_name)
+    }
+    /// Stream index of the module's symbols, None if it has none.
+    pub fn symbol_stream(&self) -> Option<usize> {
+        (self.symbol_stream_index != INVALID_STREAM_INDEX)
+            .then_some(self.symbol_stream_index as usize)
+    }
+    /// Point the module at a new symbol stream, None removes it. The sizes of
+    /// the symbols and line info must match the new stream.
+    pub fn set_symbol_stream(&mut self, index: Option<usize>) -> Result<(), Error> {
+        self.symbol_stream_index = match index {
+            Some(index) => u16::try_from(index)
+                .ok()
+                .filter(|index| *index != INVALID_STREAM_INDEX)
+                .ok_or(Error::StreamIndexTooLarge(index))?,
+            None => INVALID_STREAM_INDEX,
+        };
+        Ok(())
+    }
+}
+
+/// Iterates over the modules of a module info substream.
+#[derive(Debug, Clone)]
+pub struct ModuleInfoIter<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ModuleInfoIter<'a> {
+    /// Iterate over the modules of a module info substream.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+}
+
+impl Iterator for ModuleInfoIter<'_> {
+    type Item = Result<ModuleInfo, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.bytes.len() {
+            return None;
+        }
+        let module = ModuleInfo::read(self.bytes, &mut self.offset);
+        // Nothing after a corrupted module can be found.
+        if module.is_err() {
+            self.offset = self.bytes.len();
+        }
+        Some(module)
+    }
+}
@@ -1,14 +1,87 @@
 // Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
 
 use crate::{
-    directory::StreamDirectory, pagelist::PageList, struct_overlay_both, view::SourceView,
+    allocator::PageAllocator,
+    directory::StreamDirectory,
+    error::Error,
+    fpm::{pad_num_pages, FreePageMap},
+    msfz::{Compression, Msfz, MSFZ_MAGIC},
+    pagelist::PageList,
+    smallmsf::{SmallMsf, SMALL_MAGIC},
+    struct_overlay_both,
+    view::SourceView,
 };
-use scroll::{Error, Pread};
+use scroll::Pread;
 use static_assertions::const_assert;
+use std::{
+    fs::File,
+    io::{BufWriter, Cursor, Seek, SeekFrom, Write},
+};
 
 /// Magic bytes of the PDB file format 7.0
 pub const MAGIC: &[u8] = b"Microsoft C/C++ MSF 7.00\r\n\x1a\x44\x53\x00\x00\x00";
 pub type PageNumber = u32;
+/// Page size used when there is no page size to keep, this is what link.exe uses.
+pub const DEFAULT_PAGE_SIZE: u32 = 0x1000;
+/// Offset of the array of stream block map pages inside of the MSF header page.
+pub const BLOCK_MAP_OFFSET: usize = 0x34;
+
+/// Valid page sizes are 512, 1024, 2048, and 4096. Newer linkers can also use
+/// 8192, 16384 and 32768 byte pages to create PDB files larger than 4 GiB.
+#[inline(always)]
+pub fn is_valid_page_size(page_size: u32) -> bool {
+    matches!(page_size, 512 | 1024 | 2048 | 4096 | 8192 | 16384 | 32768)
+}
+
+/// Small (MSF 2.00) files only use page sizes of 512, 1024, 2048 and 4096.
+#[inline(always)]
+pub fn is_valid_small_page_size(page_size: u32) -> bool {
+    matches!(page_size, 512 | 1024 | 2048 | 4096)
+}
+
+/// Byte offset of a page in the file. This is 64 bits since files with big
+/// pages can be larger than 4 GiB.
+#[inline(always)]
+pub fn page_offset(pfn: PageNumber, page_size: u32) -> u64 {
+    pfn as u64 * page_size as u64
+}
+
+/// A file that commits can be written to. "Write::flush" does not make a
+/// "File" durable, so commits call "sync" to order their writes on disk.
+pub trait SyncWrite: Write + Seek {
+    /// Write out everything buffered and return once every byte written so
+    /// far is durable, like "File::sync_data".
+    fn sync(&mut self) -> std::io::Result<()>;
+}
+
+impl SyncWrite for File {
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.sync_data()
+    }
+}
+
+/// Memory has nothing to sync.
+impl<T> SyncWrite for Cursor<T>
+where
+    Cursor<T>: Write + Seek,
+{
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.flush()
+    }
+}
+
+impl<W: SyncWrite> SyncWrite for BufWriter<W> {
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.flush()?;
+        self.get_mut().sync()
+    }
+}
+
+impl<W: SyncWrite + ?Sized> SyncWrite for &mut W {
+    fn sync(&mut self) -> std::io::Result<()> {
+        (**self).sync()
+    }
+}
 
 // https://llvm.org/docs/PDB/MsfFile.html
 // struct SuperBlock {
@@ -24,6 +97,7 @@ struct_overlay_both!((pub MsfBigHeader, pub MsfBigHeaderMut) {
     // Must be equal to "Microsoft C / C++ MSF 7.00\\r\\n" followed by the bytes 1A 44 53 00 00 00.
     [0x00] magic: [u8; 32],
     // The block size of the internal file system. Valid values are 512, 1024, 2048, and 4096 bytes.
+    // Newer versions of link.exe can also use 8192, 16384 and 32768 bytes for files over 4 GiB.
     // Certain aspects of the MSF file layout vary depending on the block sizes. For the purposes of LLVM,
     // we handle only block sizes of 4KiB, and all further discussion assumes a block size of 4KiB.
     [0x20] page_size: u32,
@@ -50,39 +124,59 @@ impl<'a> MsfBigHeaderMut<'a> {
     /// How many pages are required to store N amount of bytes?
     #[inline(always)]
     pub fn pages_needed_to_store(&self, bytes: u32) -> u32 {
-        (bytes + (self.get_page_size() - 1)) / self.get_page_size()
+        bytes.div_ceil(self.get_page_size())
     }
     /// Get the page at which the stream block map exists.
     #[inline(always)]
     pub fn stream_block_map(&self) -> usize {
-        (self.get_stream_block_map() * self.get_page_size()) as usize
+        page_offset(self.get_stream_block_map(), self.get_page_size()) as usize
+    }
+    /// Size of the file according to the header.
+    #[inline(always)]
+    pub fn file_size(&self) -> u64 {
+        page_offset(self.get_num_pages(), self.get_page_size())
+    }
+    /// How many stream block map pages fit in the array at the end of the header page?
+    #[inline(always)]
+    pub fn max_block_map_pages(&self) -> u32 {
+        (self.get_page_size() - BLOCK_MAP_OFFSET as u32) / 4
     }
     /// Flush header to the buffer.
     #[inline(always)]
-    pub fn flush(&self, buff: &mut Vec<u8>) {
-        buff[0..Self::size()].copy_from_slice(&self.ptr);
+    pub fn flush(&self, buff: &mut [u8]) {
+        buff[0..Self::size()].copy_from_slice(self.ptr);
     }
 }
 
 impl<'a> MsfBigHeader<'a> {
     /// Validates the magic bytes in the header.
-    pub fn from(bytes: &'a [u8]) -> Option<Self> {
-        let header = Self::new(bytes)?;
+    pub fn from(bytes: &'a [u8]) -> Result<Self, Error> {
+        let header = Self::new(bytes).ok_or(Error::TruncatedHeader("MSF"))?;
         if header.get_magic() == MAGIC {
-            Some(header)
+            Ok(header)
         } else {
-            None
+            Err(Error::BadMagic)
         }
     }
     /// How many pages are required to store N amount of bytes?
     #[inline(always)]
     pub fn pages_needed_to_store(&self, bytes: u32) -> u32 {
-        (bytes + (self.get_page_size() - 1)) / self.get_page_size()
+        bytes.div_ceil(self.get_page_size())
     }
     /// Get the page at which the stream block map exists.
     #[inline(always)]
     pub fn stream_block_map(&self) -> usize {
-        (self.get_stream_block_map() * self.get_page_size()) as usize
+        page_offset(self.get_stream_block_map(), self.get_page_size()) as usize
+    }
+    /// Size of the file according to the header.
+    #[inline(always)]
+    pub fn file_size(&self) -> u64 {
+        page_offset(self.get_num_pages(), self.get_page_size())
+    }
+    /// How many stream block map pages fit in the array at the end of the header page?
+    #[inline(always)]
+    pub fn max_block_map_pages(&self) -> u32 {
+        (self.get_page_size() - BLOCK_MAP_OFFSET as u32) / 4
     }
 }
 
@@ -91,61 +185,292 @@ impl<'a> MsfBigHeader<'a> {
 pub struct BigMsf {
     /// Internal back buffer of the MSF file.
     pub bytes: Vec<u8>,
+    /// Zero every free page when the stream directory is flushed.
+    pub zero_free_pages: bool,
 }
 
 impl BigMsf {
     /// Create a new MSF/PDB given a copy of its bytes.
     pub fn new(bytes: Vec<u8>) -> Self {
-        Self { bytes }
+        Self {
+            bytes,
+            zero_free_pages: false,
+        }
+    }
+    /// Open a PDB of any supported container. MSF 7.00 files are used as is,
+    /// MSFZ and PDB 2.0 files are converted to MSF 7.00 files.
+    pub fn open(bytes: Vec<u8>) -> Result<Self, Error> {
+        if bytes.starts_with(MAGIC) {
+            Ok(Self::new(bytes))
+        } else if bytes.starts_with(MSFZ_MAGIC) {
+            Msfz::new(bytes).to_big_msf(DEFAULT_PAGE_SIZE)
+        } else if bytes.starts_with(SMALL_MAGIC) {
+            SmallMsf::new(bytes).to_big_msf()
+        } else {
+            Err(Error::BadMagic)
+        }
+    }
+    /// Write the streams of this MSF into a compressed MSFZ container.
+    pub fn to_msfz(&self, compression: Compression) -> Result<Msfz, Error> {
+        Msfz::from_stream_directory(&self.get_stream_directory()?, compression)
+    }
+    /// Create a new MSF/PDB which contains the streams of the directory. Every
+    /// stream is laid out again from scratch, one after another.
+    pub fn from_stream_directory(page_size: u32, mut dir: StreamDirectory) -> Result<Self, Error> {
+        if !is_valid_page_size(page_size) {
+            return Err(Error::BadPageSize(page_size));
+        }
+        // The header page and both FPM pages are all we need to start with.
+        let mut bytes = vec![0u8; page_size as usize * 3];
+        let mut header = MsfBigHeaderMut::new(&mut bytes).ok_or(Error::TruncatedHeader("MSF"))?;
+        header.set_magic(MAGIC.try_into().map_err(|_| Error::BadMagic)?);
+        header.set_page_size(page_size);
+        header.set_free_page_map(1);
+        header.set_num_pages(3);
+        dir.clear_pages(page_size);
+        let mut msf = Self::new(bytes);
+        msf.set_stream_directory(dir)?;
+        Ok(msf)
+    }
+    /// Rewrite the MSF into a fresh buffer where the header, the FPM, every stream
+    /// (in stream order), the directory and the block map are laid out one after
+    /// another. Pages which are not referenced are dropped, the content of every
+    /// stream stays the same.
+    pub fn compact(&mut self) -> Result<(), Error> {
+        let header = self.header()?;
+        let page_size = header.get_page_size();
+        let unknown = header.get_unknown();
+        let mut msf = Self::from_stream_directory(page_size, self.get_stream_directory()?)?;
+        msf.header_mut()?.set_unknown(unknown);
+        self.bytes = msf.bytes;
+        Ok(())
     }
     /// Get an immutable reference to the MSF header.
     #[inline(always)]
-    pub fn header(&self) -> Option<MsfBigHeader<'_>> {
-        MsfBigHeader::new(&self.bytes)
+    pub fn header(&self) -> Result<MsfBigHeader<'_>, Error> {
+        MsfBigHeader::new(&self.bytes).ok_or(Error::TruncatedHeader("MSF"))
     }
     /// Get a mutable reference to the MSF header.
     #[inline(always)]
-    pub fn header_mut(&mut self) -> Option<MsfBigHeaderMut<'_>> {
-        MsfBigHeaderMut::new(&mut self.bytes)
+    pub fn header_mut(&mut self) -> Result<MsfBigHeaderMut<'_>, Error> {
+        MsfBigHeaderMut::new(&mut self.bytes).ok_or(Error::TruncatedHeader("MSF"))
     }
     /// Find and parse the stream directory, return a read only mapping of it.
     pub fn get_stream_directory(&self) -> Result<StreamDirectory, Error> {
-        let header = self
-            .header()
-            .ok_or_else(|| Error::Custom(format!("Failed to parse MSF header!")))?;
-        // Get the page that contains page numbers for each page that the
+        let header = self.header()?;
+        let (view, block_map) = self.read_directory_views()?;
+        // Parse the stream directory and return it.
+        StreamDirectory::new(&self.bytes, view, block_map, &header)
+    }
+    /// Parse the stream directory without reading any stream. The pages of
+    /// every stream are known but their bytes are empty.
+    pub fn get_stream_layout(&self) -> Result<StreamDirectory, Error> {
+        let header = self.header()?;
+        let (view, block_map) = self.read_directory_views()?;
+        StreamDirectory::parse(view, block_map, &header)
+    }
+    /// Map the pages of the stream directory and of the stream block map.
+    fn read_directory_views(&self) -> Result<(SourceView, SourceView), Error> {
+        let header = MsfBigHeader::from(&self.bytes)?;
+        if !is_valid_page_size(header.get_page_size()) {
+            return Err(Error::BadPageSize(header.get_page_size()));
+        }
+        // Get the pages that contain page numbers for each page that the
         // stream directory uses. (Yes the stream directory might need multiple pages.)
-        let stream_block_map = &self.bytes[header.stream_block_map()..];
+        // Large directories need more than one of these pages, their page numbers
+        // are stored in an array which starts at "stream_block_map" in the header.
         let num_pages = header.pages_needed_to_store(header.get_stream_dir_size());
+        let num_block_map_pages = header.pages_needed_to_store(num_pages * 4);
+        if num_block_map_pages > header.max_block_map_pages() {
+            return Err(Error::DirectoryTooLarge {
+                size: header.get_stream_dir_size() as u64,
+            });
+        }
+        let mut offset = BLOCK_MAP_OFFSET;
+        let mut block_map_pages = PageList::new(header.get_page_size());
+        for _ in 0..num_block_map_pages {
+            block_map_pages.push(
+                self.bytes
+                    .gread::<u32>(&mut offset)
+                    .map_err(|_| Error::TruncatedHeader("MSF"))?,
+            );
+        }
+        let block_map =
+            SourceView::with_size(&self.bytes, block_map_pages, num_pages as usize * 4)?;
         let mut offset = 0;
         let mut pages = PageList::new(header.get_page_size());
         // Now read all of the page numbers needed into a PageList.
         for _ in 0..num_pages {
-            pages.push(stream_block_map.gread::<u32>(&mut offset)?);
+            pages.push(block_map.as_slice().gread::<u32>(&mut offset)?);
         }
         // Map the pages to a linear sequence of bytes with a known size.
-        let view = SourceView::with_size(&self.bytes, pages, header.get_stream_dir_size() as usize)
-            .ok_or_else(|| Error::Custom("Failed to parse stream directory!".to_string()))?;
-        // Parse the stream directory and return it.
-        StreamDirectory::new(&self.bytes, view, &header)
+        let view =
+            SourceView::with_size(&self.bytes, pages, header.get_stream_dir_size() as usize)?;
+        Ok((view, block_map))
+    }
+    /// Pages the current stream directory references, as an FPM where every
+    /// other page is free.
+    fn referenced_pages(&self) -> Result<FreePageMap, Error> {
+        let header = self.header()?;
+        let page_size = header.get_page_size();
+        let num_pages = std::cmp::min(
+            header.get_num_pages() as u64,
+            self.bytes.len() as u64 / page_size.max(1) as u64,
+        ) as u32;
+        let mut used = FreePageMap::new(num_pages, page_size);
+        let layout = self.get_stream_layout()?;
+        for view in layout
+            .streams
+            .iter()
+            .map(|stream| &stream.view)
+            .chain([&layout.view, &layout.block_map])
+        {
+            for pfn in view.pages.pfns.iter() {
+                used.mark_used(*pfn);
+            }
+        }
+        Ok(used)
     }
     /// Flush stream directory back to underlying bytes. Updates the MSF
-    /// header as well.
-    pub fn set_stream_directory(&mut self, mut dir: StreamDirectory) -> Result<(), Error> {
+    /// header and rebuilds the FPM as well.
+    pub fn set_stream_directory(&mut self, dir: StreamDirectory) -> Result<(), Error> {
+        self.write_stream_directory(dir, None)
+    }
+    /// Crash safe version of "set_stream_directory". Streams which changed, the
+    /// directory and the block map are written to pages that the current state of
+    /// the file does not use, and the FPM is written to the alternate FPM page.
+    /// Until the header page is written the file still holds the old state.
+    pub fn commit_stream_directory(&mut self, mut dir: StreamDirectory) -> Result<(), Error> {
+        let header = self.header()?;
+        // Pages the FPM marks as used and every page the directory references
+        // belong to the committed state. Reading the directory validates the
+        // page size before the FPM is read.
+        let referenced = self.referenced_pages()?;
+        let mut committed = FreePageMap::read(&self.bytes, &header);
+        for pfn in 0..referenced.num_pages {
+            if !referenced.is_free(pfn) {
+                committed.mark_used(pfn);
+            }
+        }
+        // Unchanged streams keep their pages, the others move to new ones.
+        for stream in dir.streams.iter_mut() {
+            let view = &stream.view;
+            let pages_needed = header.pages_needed_to_store(view.bytes.len() as u32);
+            let unchanged = view.pages.pfns.len() == pages_needed as usize
+                && view.pages.pfns.iter().all(|pfn| !committed.is_free(*pfn))
+                && SourceView::with_size(&self.bytes, view.pages.clone(), view.bytes.len())
+                    .is_ok_and(|old| old.bytes == view.bytes);
+            if !unchanged {
+                stream.view.pages.pfns.clear();
+            }
+        }
+        dir.view.pages.pfns.clear();
+        dir.block_map.pages.pfns.clear();
+        self.write_stream_directory(dir, Some(committed))
+    }
+    /// Commit the stream directory and write the changes to "file", which must
+    /// hold the current bytes of this MSF. Every changed page except the header
+    /// page is written and synced first, then the header page is written and
+    /// synced. Once this returns the new state is durable, if it fails or the
+    /// system crashes before the header page is durable the file holds the old
+    /// state.
+    pub fn commit_to<W: SyncWrite>(
+        &mut self,
+        dir: StreamDirectory,
+        file: &mut W,
+    ) -> Result<(), Error> {
+        let old_bytes = self.bytes.clone();
+        self.commit_stream_directory(dir)?;
+        let page_size = self.header()?.get_page_size() as usize;
+        for (pfn, page) in self.bytes.chunks(page_size).enumerate().skip(1) {
+            let page_start = pfn * page_size;
+            if old_bytes.get(page_start..page_start + page_size) != Some(page) {
+                file.seek(SeekFrom::Start(page_start as u64))?;
+                file.write_all(page)?;
+            }
+        }
+        file.sync()?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&self.bytes[..page_size])?;
+        file.sync()?;
+        Ok(())
+    }
+    /// Flush the stream directory, pages of the committed state are kept intact
+    /// and the FPM is flipped when "committed" is set.
+    fn write_stream_directory(
+        &mut self,
+        mut dir: StreamDirectory,
+        committed: Option<FreePageMap>,
+    ) -> Result<(), Error> {
         // Make a clone of the headers right now.
         let mut header_bytes = vec![0u8; MsfBigHeaderMut::size()];
-        header_bytes.copy_from_slice(
-            self.header()
-                .ok_or_else(|| Error::Custom(format!("Failed to parse MSF header!")))?
-                .ptr,
-        );
+        header_bytes.copy_from_slice(self.header()?.ptr);
         // Cloned mutable header which we gets updated by flush.
-        let mut header = MsfBigHeaderMut::new(&mut header_bytes)
-            .ok_or_else(|| Error::Custom(format!("Failed to parse MSF header!")))?;
+        let mut header =
+            MsfBigHeaderMut::new(&mut header_bytes).ok_or(Error::TruncatedHeader("MSF"))?;
+        let page_size = header.get_page_size();
+        if !is_valid_page_size(page_size) {
+            return Err(Error::BadPageSize(page_size));
+        }
+        if self.bytes.len() < page_size as usize {
+            return Err(Error::TruncatedHeader("MSF"));
+        }
+        if !matches!(header.get_free_page_map(), 1 | 2) {
+            return Err(Error::BadFreePageMap(header.get_free_page_map()));
+        }
+        // Pages the header claims but the buffer does not have do not exist.
+        let num_pages = std::cmp::min(
+            header.get_num_pages() as u64,
+            self.bytes.len() as u64 / page_size as u64,
+        ) as u32;
+        // What the file looks like right now. A save which leaves the header
+        // page and the directory as they were keeps the FPM as it was, link.exe
+        // for example marks the pages of the old directory in stream 0 as free.
+        // Every other save rebuilds the FPM. New files have no directory yet.
+        let previous = if num_pages == header.get_num_pages() {
+            self.read_directory_views().ok().and_then(|directory| {
+                Some((
+                    FreePageMap::read(&self.bytes, &self.header().ok()?),
+                    self.bytes[..page_size as usize].to_vec(),
+                    directory,
+                ))
+            })
+        } else {
+            None
+        };
+        // Every page not used by the directory or its streams can be handed out.
+        let mut allocator = PageAllocator::new(num_pages, page_size);
+        allocator.zero_fill = self.zero_free_pages;
+        if committed.is_some() {
+            // Write the FPM to the page the committed state does not use.
+            header.set_free_page_map(3 - header.get_free_page_map());
+            allocator.committed = committed;
+        }
         // Flush directory back to the underlying buffer.
-        dir.flush(&mut self.bytes, &mut header)?;
+        dir.flush(&mut self.bytes, &mut header, &mut allocator)?;
+        // Make sure the last interval has its FPM pages.
+        allocator.grow(pad_num_pages(allocator.num_pages(), page_size));
+        header.set_num_pages(allocator.num_pages());
+        self.bytes
+            .resize(page_offset(allocator.num_pages(), page_size) as usize, 0);
+        if allocator.zero_fill {
+            allocator.zero_free_pages(&mut self.bytes);
+        }
         // Flush updated MSF header now.
         header.flush(&mut self.bytes);
+        // Write the FPM of the pages that are actually in use.
+        let fpm = match previous {
+            Some((fpm, header_page, (view, block_map)))
+                if self.bytes[..page_size as usize] == header_page
+                    && self.read_directory_views().is_ok_and(|directory| {
+                        directory.0.bytes == view.bytes && directory.1.bytes == block_map.bytes
+                    }) =>
+            {
+                fpm
+            }
+            _ => allocator.fpm,
+        };
+        fpm.flush(&mut self.bytes, &header);
         Ok(())
     }
 }
@@ -0,0 +1,378 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use crate::{
+    directory::{read_u32, Stream, StreamDirectory, INVALID_STREAM_SIZE},
+    error::Error,
+    msf::BigMsf,
+    pagelist::PageList,
+    struct_overlay_both,
+    view::SourceView,
+};
+use static_assertions::const_assert;
+use std::io::Read;
+
+/// Magic bytes of the compressed MSFZ container.
+pub const MSFZ_MAGIC: &[u8] = b"Microsoft MSFZ Container\r\n\x1aALD\x00\x00";
+/// The only version of the MSFZ container.
+pub const MSFZ_VERSION_V0: u64 = 0;
+/// Streams are packed into chunks of (at most) this many uncompressed bytes.
+pub const MSFZ_CHUNK_SIZE: usize = 0x40_0000;
+/// Set in the high DWORD of a fragment location if the fragment lives in a chunk.
+pub const FRAGMENT_IN_CHUNK: u32 = 0x8000_0000;
+
+// https://github.com/microsoft/pdb-rs/blob/main/msfz/src/lib.rs
+struct_overlay_both!((pub MsfzHeader, pub MsfzHeaderMut) {
+    // Must be equal to "Microsoft MSFZ Container\r\n" followed by the bytes 1A 41 4C 44 00 00.
+    [0x00] magic: [u8; 32],
+    [0x20] version: u64,
+    // File offset of the (possibly compressed) stream directory.
+    [0x28] stream_dir_offset: u64,
+    // File offset of the chunk table, which is never compressed.
+    [0x30] chunk_table_offset: u64,
+    [0x38] num_streams: u32,
+    [0x3C] stream_dir_compression: u32,
+    [0x40] stream_dir_size_compressed: u32,
+    [0x44] stream_dir_size_uncompressed: u32,
+    [0x48] num_chunks: u32,
+    [0x4C] chunk_table_size: u32,
+});
+const_assert!(MsfzHeader::size() == 0x50);
+
+// Each chunk holds the data of one or more streams, compressed as one unit.
+struct_overlay_both!((pub MsfzChunkOverlay, pub MsfzChunkOverlayMut) {
+    [0x00] file_offset: u64,
+    [0x08] compression: u32,
+    [0x0C] compressed_size: u32,
+    [0x10] uncompressed_size: u32,
+});
+const_assert!(MsfzChunkOverlay::size() == 0x14);
+
+/// Compression algorithms of the chunks and the stream directory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+    Deflate,
+}
+
+impl Compression {
+    /// Parse the compression value stored in the file.
+    pub fn from_u32(value: u32) -> Result<Self, Error> {
+        match value {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Zstd),
+            2 => Ok(Self::Deflate),
+            _ => Err(Error::Unsupported(format!("MSFZ compression {value}"))),
+        }
+    }
+    /// The compression value stored in the file.
+    pub fn to_u32(self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Zstd => 1,
+            Self::Deflate => 2,
+        }
+    }
+    /// Compress a buffer.
+    pub fn compress(self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Self::None => bytes.to_vec(),
+            Self::Zstd => ruzstd::encoding::compress_to_vec(
+                bytes,
+                ruzstd::encoding::CompressionLevel::Fastest,
+            ),
+            Self::Deflate => miniz_oxide::deflate::compress_to_vec(bytes, 6),
+        }
+    }
+    /// The most bytes "compressed_size" bytes can decompress to. Deflate can
+    /// not do better than 1032:1 and a zstd RLE block needs 4 bytes for 128 KiB.
+    pub fn max_uncompressed_size(self, compressed_size: u64) -> u64 {
+        let ratio = match self {
+            Self::None => 1,
+            Self::Zstd => 0x8000,
+            Self::Deflate => 1032,
+        };
+        compressed_size.saturating_mul(ratio)
+    }
+    /// Decompress a buffer, the size of the result must be known up front.
+    /// Sizes the buffer can not decompress to are rejected before anything is
+    /// decompressed.
+    pub fn decompress(self, bytes: &[u8], uncompressed_size: usize) -> Result<Vec<u8>, Error> {
+        let limit = self.max_uncompressed_size(bytes.len() as u64);
+        if uncompressed_size as u64 > limit {
+            return Err(Error::TooManyBytes {
+                size: uncompressed_size as u64,
+                limit,
+            });
+        }
+        let decompressed = match self {
+            Self::None => bytes.to_vec(),
+            Self::Zstd => {
+                let decoder = ruzstd::decoding::StreamingDecoder::new(bytes)
+                    .map_err(|e| Error::Decompress(e.to_string()))?;
+                // Never trust the size in the file, read at most one byte more.
+                let mut decompressed = Vec::new();
+                decoder
+                    .take(uncompressed_size as u64 + 1)
+                    .read_to_end(&mut decompressed)
+                    .map_err(|e| Error::Decompress(e.to_string()))?;
+                decompressed
+            }
+            Self::Deflate => {
+                miniz_oxide::inflate::decompress_to_vec_with_limit(bytes, uncompressed_size)
+                    .map_err(|e| Error::Decompress(e.to_string()))?
+            }
+        };
+        if decompressed.len() != uncompressed_size {
+            return Err(Error::Decompress(format!(
+                "Decompressed {:#x} bytes but expected {uncompressed_size:#x}!",
+                decompressed.len()
+            )));
+        }
+        Ok(decompressed)
+    }
+}
+
+/// High level abstraction of an MSFZ file. The streams of an MSFZ file are
+/// lifted into the same StreamDirectory as the streams of an MSF file.
+#[derive(Debug, Default)]
+pub struct Msfz {
+    /// Internal back buffer of the MSFZ file.
+    pub bytes: Vec<u8>,
+}
+
+impl Msfz {
+    /// Create a new MSFZ given a copy of its bytes.
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes }
+    }
+    /// Get an immutable reference to the MSFZ header, validates the magic bytes.
+    #[inline(always)]
+    pub fn header(&self) -> Result<MsfzHeader<'_>, Error> {
+        let header = MsfzHeader::new(&self.bytes).ok_or(Error::TruncatedHeader("MSFZ"))?;
+        if header.get_magic() == MSFZ_MAGIC {
+            Ok(header)
+        } else {
+            Err(Error::BadMagic)
+        }
+    }
+    /// Get a slice of the file, fails if the slice is out of bounds.
+    fn slice(&self, offset: u64, size: u64) -> Result<&[u8], Error> {
+        offset
+            .checked_add(size)
+            .and_then(|end| self.bytes.get(offset as usize..end as usize))
+            .ok_or(Error::OutOfBounds { offset, size })
+    }
+    /// Decompress the stream directory and every chunk a stream uses, then lift
+    /// every stream.
+    pub fn get_stream_directory(&self) -> Result<StreamDirectory, Error> {
+        let header = self.header()?;
+        if header.get_version() != MSFZ_VERSION_V0 {
+            return Err(Error::Unsupported(format!(
+                "MSFZ version {}",
+                header.get_version()
+            )));
+        }
+        // Chunks are only decompressed once a fragment uses them.
+        let chunk_table = self.slice(
+            header.get_chunk_table_offset(),
+            header.get_chunk_table_size() as u64,
+        )?;
+        let mut chunk_entries = Vec::new();
+        for i in 0..header.get_num_chunks() as usize {
+            let chunk = chunk_table
+                .get(i * MsfzChunkOverlay::size()..)
+                .and_then(MsfzChunkOverlay::new)
+                .ok_or(Error::OutOfBounds {
+                    offset: header.get_chunk_table_offset() + (i * MsfzChunkOverlay::size()) as u64,
+                    size: MsfzChunkOverlay::size() as u64,
+                })?;
+            chunk_entries.push(chunk);
+        }
+        let mut chunks = vec![None::<Vec<u8>>; chunk_entries.len()];
+        // Decompress the stream directory.
+        let directory = Compression::from_u32(header.get_stream_dir_compression())?.decompress(
+            self.slice(
+                header.get_stream_dir_offset(),
+                header.get_stream_dir_size_compressed() as u64,
+            )?,
+            header.get_stream_dir_size_uncompressed() as usize,
+        )?;
+        // Every stream is a list of fragments terminated by a zero size.
+        // Nil streams only have a single INVALID_STREAM_SIZE.
+        let mut offset = 0;
+        let mut streams = Vec::<Stream>::new();
+        // Fragments of a valid file never overlap, so together they cannot be
+        // larger than the chunks and the file.
+        let limit = chunk_entries
+            .iter()
+            .map(|chunk| chunk.get_uncompressed_size() as u64)
+            .sum::<u64>()
+            + self.bytes.len() as u64;
+        let mut size_of_streams = 0u64;
+        for index in 0..header.get_num_streams() as usize {
+            let mut size = read_u32(&directory, &mut offset)?;
+            if size == INVALID_STREAM_SIZE {
+                streams.push(Stream {
+                    original_stream_size: INVALID_STREAM_SIZE,
+                    ..Default::default()
+                });
+                continue;
+            }
+            let mut bytes = Vec::<u8>::new();
+            while size != 0 {
+                size_of_streams += size as u64;
+                if size_of_streams > limit {
+                    return Err(Error::TooManyBytes {
+                        size: size_of_streams,
+                        limit,
+                    });
+                }
+                let location_lo = read_u32(&directory, &mut offset)?;
+                let location_hi = read_u32(&directory, &mut offset)?;
+                if location_hi & FRAGMENT_IN_CHUNK != 0 {
+                    let chunk_index = (location_hi & !FRAGMENT_IN_CHUNK) as usize;
+                    let start = location_lo as usize;
+                    let truncated = || Error::TruncatedStream {
+                        index,
+                        offset: bytes.len(),
+                    };
+                    let entry = chunk_entries.get(chunk_index).ok_or_else(truncated)?;
+                    let end = start
+                        .checked_add(size as usize)
+                        .filter(|end| *end <= entry.get_uncompressed_size() as usize)
+                        .ok_or_else(truncated)?;
+                    if chunks[chunk_index].is_none() {
+                        let compressed = self
+                            .slice(entry.get_file_offset(), entry.get_compressed_size() as u64)?;
+                        chunks[chunk_index] = Some(
+                            Compression::from_u32(entry.get_compression())?
+                                .decompress(compressed, entry.get_uncompressed_size() as usize)?,
+                        );
+                    }
+                    let fragment = chunks[chunk_index]
+                        .as_deref()
+                        .and_then(|chunk| chunk.get(start..end))
+                        .ok_or_else(truncated)?;
+                    bytes.extend_from_slice(fragment);
+                } else {
+                    let file_offset = (location_hi as u64) << 32 | location_lo as u64;
+                    bytes.extend_from_slice(self.slice(file_offset, size as u64)?);
+                }
+                size = read_u32(&directory, &mut offset)?;
+            }
+            streams.push(Stream {
+                original_stream_size: bytes.len() as u32,
+                view: SourceView {
+                    bytes,
+                    pages: PageList::default(),
+                },
+            });
+        }
+        Ok(StreamDirectory {
+            streams,
+            ..Default::default()
+        })
+    }
+    /// Create an MSFZ file which holds the streams of the directory. Streams are
+    /// packed into chunks which are compressed with "compression".
+    pub fn from_stream_directory(
+        dir: &StreamDirectory,
+        compression: Compression,
+    ) -> Result<Self, Error> {
+        // Pack the streams into chunks and describe them in the stream directory.
+        let mut chunks = vec![Vec::<u8>::new()];
+        let mut directory = Vec::<u8>::new();
+        for stream in dir.streams.iter() {
+            let bytes = stream.view.as_slice();
+            if stream.is_nil() {
+                directory.extend_from_slice(&INVALID_STREAM_SIZE.to_le_bytes());
+                continue;
+            }
+            let mut remaining = bytes;
+            while !remaining.is_empty() {
+                if chunks
+                    .last()
+                    .is_some_and(|chunk| chunk.len() >= MSFZ_CHUNK_SIZE)
+                {
+                    chunks.push(Vec::new());
+                }
+                let chunk_index = chunks.len() - 1;
+                let chunk = &mut chunks[chunk_index];
+                let size = std::cmp::min(remaining.len(), MSFZ_CHUNK_SIZE - chunk.len());
+                directory.extend_from_slice(&(size as u32).to_le_bytes());
+                directory.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+                directory
+                    .extend_from_slice(&(chunk_index as u32 | FRAGMENT_IN_CHUNK).to_le_bytes());
+                chunk.extend_from_slice(&remaining[..size]);
+                remaining = &remaining[size..];
+            }
+            directory.extend_from_slice(&0u32.to_le_bytes());
+        }
+        // Header, then the chunks, then the stream directory, then the chunk table.
+        let mut bytes = vec![0u8; MsfzHeader::size()];
+        let mut chunk_table = vec![0u8; chunks.len() * MsfzChunkOverlay::size()];
+        for (i, chunk) in chunks.iter().enumerate() {
+            let compressed = compression.compress(chunk);
+            let mut entry =
+                MsfzChunkOverlayMut::new(&mut chunk_table[i * MsfzChunkOverlay::size()..])
+                    .ok_or(Error::TruncatedHeader("MSFZ chunk"))?;
+            entry.set_file_offset(bytes.len() as u64);
+            entry.set_compression(compression.to_u32());
+            entry.set_compressed_size(compressed.len() as u32);
+            entry.set_uncompressed_size(chunk.len() as u32);
+            bytes.extend_from_slice(&compressed);
+        }
+        let stream_dir_offset = bytes.len() as u64;
+        let compressed_directory = compression.compress(&directory);
+        bytes.extend_from_slice(&compressed_directory);
+        let chunk_table_offset = bytes.len() as u64;
+        bytes.extend_from_slice(&chunk_table);
+        let mut header = MsfzHeaderMut::new(&mut bytes).ok_or(Error::TruncatedHeader("MSFZ"))?;
+        header.set_magic(MSFZ_MAGIC.try_into().map_err(|_| Error::BadMagic)?);
+        header.set_version(MSFZ_VERSION_V0);
+        header.set_stream_dir_offset(stream_dir_offset);
+        header.set_chunk_table_offset(chunk_table_offset);
+        header.set_num_streams(dir.streams.len() as u32);
+        header.set_stream_dir_compression(compression.to_u32());
+        header.set_stream_dir_size_compressed(compressed_directory.len() as u32);
+        header.set_stream_dir_size_uncompressed(directory.len() as u32);
+        header.set_num_chunks(chunks.len() as u32);
+        header.set_chunk_table_size(chunk_table.len() as u32);
+        Ok(Self { bytes })
+    }
+    /// Convert to an MSF 7.00 file with the given page size.
+    pub fn to_big_msf(&self, page_size: u32) -> Result<BigMsf, Error> {
+        BigMsf::from_stream_directory(page_size, self.get_stream_directory()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Compression;
+    use crate::error::Error;
+
+    /// Every compression must give back what it was given.
+    #[test]
+    fn compression_round_trip() {
+        let bytes = (0..0x10000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect::<Vec<_>>();
+        for compression in [Compression::None, Compression::Zstd, Compression::Deflate] {
+            let compressed = compression.compress(&bytes);
+            assert_eq!(
+                compression.decompress(&compressed, bytes.len()).unwrap(),
+                bytes
+            );
+            assert!(compression
+                .decompress(&compressed, bytes.len() + 1)
+                .is_err());
+            // Sizes no data of this length can reach are refused up front.
+            assert!(matches!(
+                compression.decompress(&compressed[..4], u32::MAX as usize),
+                Err(Error::TooManyBytes { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{directory::PDB_INFO_STREAM_INDEX, error::Error, hash::hash_string_v1};
+use scroll::{Pread, Pwrite};
+
+/// Capacity of a new hash table, same as LLVM.
+const DEFAULT_CAPACITY: u32 = 8;
+
+/// Maps names like "/names" or "/LinkInfo" to stream indices. Stored in the PDB
+/// info stream as a string buffer followed by a serialized hash table, whose
+/// keys are offsets of the names in the string buffer.
+/// https://llvm.org/docs/PDB/HashTable.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamedStreamMap {
+    /// Every name followed by a null terminator. Removed names stay.
+    pub strings: Vec<u8>,
+    /// Number of buckets of the hash table.
+    pub capacity: u32,
+    /// (Offset of the name, stream index) of every present bucket.
+    pub buckets: BTreeMap<u32, (u32, u32)>,
+    /// Buckets whose entry was removed, lookups probe past them.
+    pub deleted: BTreeSet<u32>,
+    /// Written by MSPDB after the hash table, always 0.
+    pub ni_mac: u32,
+}
+
+impl Default for NamedStreamMap {
+    fn default() -> Self {
+        Self {
+            strings: Vec::new(),
+            capacity: DEFAULT_CAPACITY,
+            buckets: BTreeMap::new(),
+            deleted: BTreeSet::new(),
+            ni_mac: 0,
+        }
+    }
+}
+
+/// Names are hashed with hashStringV1 truncated to 16 bits.
+fn hash_name(name: &[u8]) -> u32 {
+    hash_string_v1(name) as u16 as u32
+}
+
+impl NamedStreamMap {
+    /// Parse the named stream map at "offset" of the PDB info stream.
+    pub fn read(bytes: &[u8], offset: &mut usize) -> Result<Self, Error> {
+        let truncated = |offset: usize| Error::TruncatedStream {
+            index: PDB_INFO_STREAM_INDEX,
+            offset,
+        };
+        let read_u32 = |offset: &mut usize| {
+            let start = *offset;
+            bytes.gread::<u32>(offset).map_err(|_| truncated(start))
+        };
+        let string_buffer_size = read_u32(offset)? as usize;
+        let strings = bytes
+            .get(*offset..)
+            .and_then(|bytes| bytes.get(..string_buffer_size))
+            .ok_or(truncated(*offset))?
+            .to_vec();
+        *offset += string_buffer_size;
+        let size = read_u32(offset)?;
+        let capacity = read_u32(offset)?;
+        // A table this large needs more memory to write back than its stream
+        // could ever hold.
+        if capacity.div_ceil(32) as usize > bytes.len() {
+            return Err(Error::BadHashTable { size, capacity });
+        }
+        // Present and deleted bit vectors, a word count followed by the words.
+        let mut bit_vectors = [BTreeSet::<u32>::new(), BTreeSet::<u32>::new()];
+        for bits in bit_vectors.iter_mut() {
+            let num_words = read_u32(offset)?;
+            for word_index in 0..num_words as u64 {
+                let word = read_u32(offset)?;
+                for bit in (0..32).filter(|bit| word & (1 << bit) != 0) {
+                    // Buckets past the capacity do not exist.
+                    let bucket = u32::try_from(word_index * 32 + bit)
+                        .ok()
+                        .filter(|bucket| *bucket < capacity)
+                        .ok_or(Error::BadHashTable { size, capacity })?;
+                    bits.insert(bucket);
+                }
+            }
+        }
+        let [present, deleted] = bit_vectors;
+        if present.len() != size as usize {
+            return Err(Error::BadHashTable { size, capacity });
+        }
+        let mut buckets = BTreeMap::new();
+        for bucket in present {
+            let key = read_u32(offset)?;
+            let value = read_u32(offset)?;
+            buckets.insert(bucket, (key, value));
+        }
+        let ni_mac = read_u32(offset)?;
+        Ok(Self {
+            strings,
+            capacity,
+            buckets,
+            deleted,
+            ni_mac,
+        })
+    }
+    /// Serialize the named stream map. Both bit vectors are written with a word
+    /// for every 32 buckets like MSPDB does.
+    pub fn to_vec(&self) -> Result<Vec<u8>, Error> {
+        let num_words = self.capacity.div_ceil(32) as usize;
+        let mut bytes =
+            vec![0u8; 4 + self.strings.len() + 16 + num_words * 8 + self.buckets.len() * 8 + 4];
+        let mut offset = 0;
+        bytes.gwrite::<u32>(self.strings.len() as u32, &mut offset)?;
+        bytes[offset..offset + self.strings.len()].copy_from_slice(&self.strings);
+        offset += self.strings.len();
+        bytes.gwrite::<u32>(self.buckets.len() as u32, &mut offset)?;
+        bytes.gwrite::<u32>(self.capacity, &mut offset)?;
+        for bits in [
+            self.buckets.keys().copied().collect::<BTreeSet<u32>>(),
+            self.deleted.clone(),
+        ] {
+            let mut words = vec![0u32; num_words];
+            for bit in bits {
+                words[(bit / 32) as usize] |= 1 << (bit % 32);
+            }
+            bytes.gwrite::<u32>(num_words as u32, &mut offset)?;
+            for word in words {
+                bytes.gwrite::<u32>(word, &mut offset)?;
+            }
+        }
+        for (key, value) in self.buckets.values() {
+            bytes.gwrite::<u32>(*key, &mut offset)?;
+            bytes.gwrite::<u32>(*value, &mut offset)?;
+        }
+        bytes.gwrite::<u32>(self.ni_mac, &mut offset)?;
+        Ok(bytes)
+    }
+    /// The name at "key" in the string buffer.
+    fn name(&self, key: u32) -> &[u8] {
+        let name = self.strings.get(key as usize..).unwrap_or_default();
+        &name[..name
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(name.len())]
+    }
+    /// The bucket of "name", or the first bucket it can be inserted at.
+    fn find(&self, name: &[u8]) -> Result<u32, Option<u32>> {
+        if self.capacity == 0 {
+            return Err(None);
+        }
+        let start = hash_name(name) % self.capacity;
+        let mut bucket = start;
+        let mut first_unused = None;
+        loop {
+            match self.buckets.get(&bucket) {
+                Some((key, _)) if self.name(*key) == name => return Ok(bucket),
+                Some(_) => {}
+                None => {
+                    first_unused = first_unused.or(Some(bucket));
+                    // Nothing was ever inserted past a bucket which was never used.
+                    if !self.deleted.contains(&bucket) {
+                        return Err(first_unused);
+                    }
+                }
+            }
+            bucket = (bucket + 1) % self.capacity;
+            if bucket == start {
+                return Err(first_unused);
+            }
+        }
+    }
+    /// Get the stream index of a name.
+    pub fn get(&self, name: &str) -> Option<u32> {
+        let bucket = self.find(name.as_bytes()).ok()?;
+        self.buckets.get(&bucket).map(|(_, value)| *value)
+    }
+    /// Every (name, stream index) pair in bucket order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.buckets.values().map(|(key, value)| {
+            (
+                std::str::from_utf8(self.name(*key)).unwrap_or_default(),
+                *value,
+            )
+        })
+    }
+    /// Map a name to a stream index, returns the stream index it had before.
+    /// The hash table grows like LLVM's once it is two thirds full.
+    pub fn insert(&mut self, name: &str, stream_index: u32) -> Option<u32> {
+        let bucket = match self.find(name.as_bytes()) {
+            Ok(bucket) => {
+                let entry = self.buckets.get_mut(&bucket)?;
+                return Some(std::mem::replace(&mut entry.1, stream_index));
+            }
+            Err(Some(bucket)) => bucket,
+            Err(None) => {
+                self.grow(true);
+                return self.insert(name, stream_index);
+            }
+        };
+        let key = self.strings.len() as u32;
+        self.strings.extend_from_slice(name.as_bytes());
+        self.strings.push(0);
+        self.buckets.insert(bucket, (key, stream_index));
+        self.deleted.remove(&bucket);
+        self.grow(false);
+        None
+    }
+    /// Remove a name, returns its stream index. The name stays in the string
+    /// buffer and its bucket is marked as deleted.
+    pub fn remove(&mut self, name: &str) -> Option<u32> {
+        let bucket = self.find(name.as_bytes()).ok()?;
+        let (_, value) = self.buckets.remove(&bucket)?;
+        self.deleted.insert(bucket);
+        Some(value)
+    }
+    /// Rehash every entry into a larger table once the table is too full.
+    fn grow(&mut self, force: bool) {
+        let max_load = (self.capacity as u64 * 2 / 3 + 1) as u32;
+        if !force && (self.buckets.len() as u64) < max_load as u64 {
+            return;
+        }
+        let entries = std::mem::take(&mut self.buckets);
+        self.deleted.clear();
+        self.capacity = max_load.saturating_mul(2).max(DEFAULT_CAPACITY);
+        for (key, value) in entries.into_values() {
+            if let Err(Some(bucket)) = self.find(self.name(key)) {
+                self.buckets.insert(bucket, (key, value));
+            }
+        }
+    }
+}
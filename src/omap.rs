@@ -1,12 +1,13 @@
 // Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
 
-use scroll::{Error, Pwrite};
+use crate::error::Error;
+use scroll::{Pread, Pwrite};
 use std::{cmp::Ordering, collections::BTreeSet};
 
 /// (Source -> Target)
 /// Entries are used to map code from one layout to another.
 /// Refer to the read of this project for OMAP info.
-#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct OmapEntry(pub u32, pub u32);
 
 impl Ord for OmapEntry {
@@ -15,11 +16,35 @@ impl Ord for OmapEntry {
     }
 }
 
+impl PartialOrd for OmapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 /// OMAP stream, used for both "to" and "from" mappings.
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct OmapStream(pub BTreeSet<OmapEntry>);
 
 impl OmapStream {
+    /// Parse the entries of an OMAP stream. The stream must hold whole entries,
+    /// of entries with the same source only the first is kept.
+    pub fn new(bytes: &[u8]) -> Result<Self, Error> {
+        if !bytes.len().is_multiple_of(8) {
+            return Err(Error::OutOfBounds {
+                offset: (bytes.len() - bytes.len() % 8) as u64,
+                size: 8,
+            });
+        }
+        let mut omap_stream = Self::default();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let source = bytes.gread::<u32>(&mut offset)?;
+            let target = bytes.gread::<u32>(&mut offset)?;
+            omap_stream.0.insert(OmapEntry(source, target));
+        }
+        Ok(omap_stream)
+    }
     /// Convert the Omap stream to bytes.
     pub fn to_vec(&self) -> Result<Vec<u8>, Error> {
         let mut buff = vec![0u8; self.0.len() * 8];
@@ -0,0 +1,250 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::io::SeekFrom;
+
+use crate::{
+    allocator::PageAllocator,
+    directory::{write_directory, Stream, StreamDirectory, INVALID_STREAM_SIZE},
+    error::Error,
+    fpm::pad_num_pages,
+    msf::{is_valid_page_size, MsfBigHeader, MsfBigHeaderMut, SyncWrite, BLOCK_MAP_OFFSET},
+    pagelist::PageList,
+    source::PageSource,
+    view::SourceView,
+};
+use scroll::{Pread, Pwrite};
+
+/// MSF/PDB file whose pages are read from a "PageSource" on demand. Only the
+/// header and the stream directory are read when opening it, streams are read
+/// when they are first touched. Use this instead of "BigMsf" for huge files.
+#[derive(Debug)]
+pub struct PagedMsf<S> {
+    /// Where the pages of the file are read from.
+    pub source: S,
+    /// Copy of the header page, which also holds the block map array.
+    header_page: Vec<u8>,
+    /// The stream directory, the bytes of streams which were not read are empty.
+    dir: StreamDirectory,
+    /// Which streams have been read from the source.
+    loaded: Vec<bool>,
+    /// Which streams have been handed out mutably and must be written on flush.
+    dirty: Vec<bool>,
+    /// Pages of the file, including the ones a flush added after the source.
+    num_pages: u32,
+}
+
+impl<S: PageSource> PagedMsf<S> {
+    /// Read the header and the stream directory, none of the streams are read.
+    pub fn open(mut source: S) -> Result<Self, Error> {
+        let mut header_page = vec![0u8; MsfBigHeader::size()];
+        source.read_at(0, &mut header_page)?;
+        let page_size = MsfBigHeader::from(&header_page)?.get_page_size();
+        if !is_valid_page_size(page_size) {
+            return Err(Error::BadPageSize(page_size));
+        }
+        header_page.resize(page_size as usize, 0);
+        source.read_at(0, &mut header_page)?;
+        let header = MsfBigHeader::from(&header_page)?;
+        // Same as "BigMsf::get_stream_directory" but the pages come from the source.
+        let num_pages = header.pages_needed_to_store(header.get_stream_dir_size());
+        let num_block_map_pages = header.pages_needed_to_store(num_pages * 4);
+        if num_block_map_pages > header.max_block_map_pages() {
+            return Err(Error::DirectoryTooLarge {
+                size: header.get_stream_dir_size() as u64,
+            });
+        }
+        let mut offset = BLOCK_MAP_OFFSET;
+        let mut block_map_pages = PageList::new(page_size);
+        for _ in 0..num_block_map_pages {
+            block_map_pages.push(header_page.gread::<u32>(&mut offset)?);
+        }
+        let block_map =
+            SourceView::from_source(&mut source, block_map_pages, num_pages as usize * 4)?;
+        let mut offset = 0;
+        let mut pages = PageList::new(page_size);
+        for _ in 0..num_pages {
+            pages.push(block_map.as_slice().gread::<u32>(&mut offset)?);
+        }
+        let view =
+            SourceView::from_source(&mut source, pages, header.get_stream_dir_size() as usize)?;
+        let dir = StreamDirectory::parse(view, block_map, &header)?;
+        let num_streams = dir.streams.len();
+        // Pages the header claims but the source does not have do not exist.
+        let num_pages = std::cmp::min(
+            header.get_num_pages() as u64,
+            source.len() / page_size as u64,
+        ) as u32;
+        Ok(Self {
+            source,
+            header_page,
+            dir,
+            loaded: vec![false; num_streams],
+            dirty: vec![false; num_streams],
+            num_pages,
+        })
+    }
+    /// Get an immutable reference to the MSF header.
+    #[inline(always)]
+    pub fn header(&self) -> Result<MsfBigHeader<'_>, Error> {
+        MsfBigHeader::new(&self.header_page).ok_or(Error::TruncatedHeader("MSF"))
+    }
+    /// Number of streams in the stream directory.
+    #[inline(always)]
+    pub fn num_streams(&self) -> usize {
+        self.dir.streams.len()
+    }
+    /// Has the stream been read from the source yet?
+    #[inline(always)]
+    pub fn is_loaded(&self, index: usize) -> bool {
+        self.loaded.get(index).copied().unwrap_or(false)
+    }
+    /// Get a stream, it is read from the source the first time it is touched.
+    pub fn stream(&mut self, index: usize) -> Result<&Stream, Error> {
+        self.load(index)?;
+        Ok(&self.dir.streams[index])
+    }
+    /// Get a mutable stream, it is written back on the next flush.
+    pub fn stream_mut(&mut self, index: usize) -> Result<&mut Stream, Error> {
+        self.load(index)?;
+        self.dirty[index] = true;
+        Ok(&mut self.dir.streams[index])
+    }
+    /// Read the bytes of a stream from the source if they were not read yet.
+    fn load(&mut self, index: usize) -> Result<(), Error> {
+        let stream = self
+            .dir
+            .streams
+            .get_mut(index)
+            .ok_or(Error::MissingStream(index))?;
+        if !self.loaded[index] {
+            if stream.original_stream_size != INVALID_STREAM_SIZE {
+                stream.view = SourceView::from_source(
+                    &mut self.source,
+                    stream.view.pages.clone(),
+                    stream.original_stream_size as usize,
+                )
+                .map_err(|e| stream.truncated(index, e))?;
+            }
+            self.loaded[index] = true;
+        }
+        Ok(())
+    }
+    /// Write the streams which were changed, the stream directory, the FPM and
+    /// the header page to "out". Streams which were not changed are not written,
+    /// so "out" must hold the same file as the source, or the file the last
+    /// flush wrote. This writes in place and is not crash safe: changed streams
+    /// and the stream directory keep their pages and the active FPM is
+    /// overwritten, use "BigMsf::commit_stream_directory" when that matters.
+    pub fn flush<W: SyncWrite>(&mut self, out: &mut W) -> Result<(), Error> {
+        let mut header_page = self.header_page.clone();
+        let mut header =
+            MsfBigHeaderMut::new(&mut header_page).ok_or(Error::TruncatedHeader("MSF"))?;
+        if !matches!(header.get_free_page_map(), 1 | 2) {
+            return Err(Error::BadFreePageMap(header.get_free_page_map()));
+        }
+        let page_size = header.get_page_size();
+        let mut allocator = PageAllocator::new(self.num_pages, page_size);
+        // Pages of unchanged streams stay where they are.
+        for (stream, dirty) in self.dir.streams.iter_mut().zip(self.dirty.iter()) {
+            if *dirty {
+                stream.view.reserve(&mut allocator);
+            } else {
+                for pfn in stream.view.pages.pfns.iter() {
+                    allocator.mark_used(*pfn);
+                }
+            }
+        }
+        let sizes = self
+            .dir
+            .streams
+            .iter()
+            .zip(self.dirty.iter())
+            .map(|(stream, dirty)| match *dirty {
+                false => stream.original_stream_size,
+                true if stream.is_nil() => INVALID_STREAM_SIZE,
+                true => stream.view.bytes.len() as u32,
+            })
+            .collect::<Vec<u32>>();
+        let stream_directory_size = 4
+            + sizes.len() as u32 * 4
+            + sizes
+                .iter()
+                .filter(|size| **size != INVALID_STREAM_SIZE)
+                .map(|size| header.pages_needed_to_store(*size) * 4)
+                .sum::<u32>();
+        let directory_pages = header.pages_needed_to_store(stream_directory_size);
+        let block_map_pages = header.pages_needed_to_store(directory_pages * 4);
+        if block_map_pages > header.max_block_map_pages() {
+            return Err(Error::DirectoryTooLarge {
+                size: stream_directory_size as u64,
+            });
+        }
+        self.dir
+            .view
+            .bytes
+            .resize(stream_directory_size as usize, 0);
+        self.dir.block_map.bytes.clear();
+        self.dir
+            .block_map
+            .bytes
+            .resize((block_map_pages * page_size) as usize, 0);
+        self.dir.view.reserve(&mut allocator);
+        self.dir.block_map.reserve(&mut allocator);
+        // Only the streams that changed are written.
+        for (stream, dirty) in self.dir.streams.iter_mut().zip(self.dirty.iter()) {
+            if *dirty {
+                stream.view.write_to(out, &mut allocator)?;
+            }
+        }
+        write_directory(
+            &mut self.dir.view.bytes,
+            self.dir
+                .streams
+                .iter()
+                .zip(sizes.iter())
+                .map(|(stream, size)| (*size, stream.view.pages.pfns.as_slice())),
+        )?;
+        self.dir.view.write_to(out, &mut allocator)?;
+        let mut offset = 0;
+        for pfn in self.dir.view.pages.pfns.iter() {
+            self.dir.block_map.bytes.gwrite::<u32>(*pfn, &mut offset)?;
+        }
+        self.dir.block_map.write_to(out, &mut allocator)?;
+        // Update the header and the block map array in the header page.
+        allocator.grow(pad_num_pages(allocator.num_pages(), page_size));
+        header.set_num_pages(allocator.num_pages());
+        header.set_stream_dir_size(stream_directory_size);
+        header.set_stream_block_map(self.dir.block_map.pages.pfns[0]);
+        allocator.fpm.write_to(out, &header)?;
+        let file_size = header.file_size();
+        header.flush(&mut self.header_page);
+        let block_map_array = &mut self.header_page[BLOCK_MAP_OFFSET..];
+        block_map_array.fill(0);
+        let mut offset = 0;
+        for pfn in self.dir.block_map.pages.pfns.iter() {
+            block_map_array.gwrite::<u32>(*pfn, &mut offset)?;
+        }
+        // Pages which were never written still have to exist.
+        let end = out.seek(SeekFrom::End(0))?;
+        if end < file_size {
+            out.write_all(&vec![0u8; (file_size - end) as usize])?;
+        }
+        // The header page goes last, so it never points at pages not written yet.
+        out.sync()?;
+        out.seek(SeekFrom::Start(0))?;
+        out.write_all(&self.header_page)?;
+        out.sync()?;
+        for ((stream, dirty), size) in self
+            .dir
+            .streams
+            .iter_mut()
+            .zip(self.dirty.iter_mut())
+            .zip(sizes)
+        {
+            stream.original_stream_size = size;
+            *dirty = false;
+        }
+        self.num_pages = allocator.num_pages();
+        Ok(())
+    }
+}
@@ -17,7 +17,7 @@ pub struct SourceSlice {
 /// List of pages used by a stream
 #[derive(Debug, Default, Clone)]
 pub struct PageList {
-    /// Valid values are 512, 1024, 2048, and 4096
+    /// Valid values are 512, 1024, 2048, 4096, 8192, 16384 and 32768
     pub page_size: u32,
     /// All of the page ranges.
     pub pfns: Vec<PageNumber>,
@@ -38,7 +38,12 @@ impl PageList {
     }
     /// Return the total length of this PageList.
     #[inline(always)]
-    pub fn len(&self) -> u32 {
-        self.pfns.len() as u32 * self.page_size
+    pub fn len(&self) -> u64 {
+        self.pfns.len() as u64 * self.page_size as u64
+    }
+    /// Returns true if there are no pages in this PageList.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.pfns.is_empty()
     }
 }
@@ -0,0 +1,154 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use crate::{
+    dbi::DbiStream,
+    directory::{StreamDirectory, DBI_STREAM_INDEX, PDB_INFO_STREAM_INDEX},
+    error::Error,
+    namedstreammap::NamedStreamMap,
+};
+use scroll::{Pread, Pwrite};
+
+/// Version of the PDB info stream written by VC 7.0 and newer.
+pub const PDB_INFO_VERSION_VC70: u32 = 20000404;
+/// Feature code of PDB files written by VC 11.0.
+pub const PDB_FEATURE_VC110: u32 = 20091201;
+/// Feature code of PDB files which have an IPI stream.
+pub const PDB_FEATURE_VC140: u32 = 20140508;
+/// Feature code of PDB files whose types were not merged, "NOTM".
+pub const PDB_FEATURE_NO_TYPE_MERGE: u32 = 0x4D544F4E;
+/// Feature code of PDB files linked with /DEBUG:FASTLINK, "MINI".
+pub const PDB_FEATURE_MINIMAL_DEBUG_INFO: u32 = 0x494E494D;
+
+/// Feature codes at the end of the PDB info stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdbFeature {
+    Vc110,
+    Vc140,
+    NoTypeMerge,
+    MinimalDebugInfo,
+    /// Codes this library does not know are kept as they are.
+    Unknown(u32),
+}
+
+impl PdbFeature {
+    /// Parse the feature code stored in the file.
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            PDB_FEATURE_VC110 => Self::Vc110,
+            PDB_FEATURE_VC140 => Self::Vc140,
+            PDB_FEATURE_NO_TYPE_MERGE => Self::NoTypeMerge,
+            PDB_FEATURE_MINIMAL_DEBUG_INFO => Self::MinimalDebugInfo,
+            _ => Self::Unknown(value),
+        }
+    }
+    /// The feature code stored in the file.
+    pub fn to_u32(self) -> u32 {
+        match self {
+            Self::Vc110 => PDB_FEATURE_VC110,
+            Self::Vc140 => PDB_FEATURE_VC140,
+            Self::NoTypeMerge => PDB_FEATURE_NO_TYPE_MERGE,
+            Self::MinimalDebugInfo => PDB_FEATURE_MINIMAL_DEBUG_INFO,
+            Self::Unknown(value) => value,
+        }
+    }
+}
+
+/// High level abstraction of the PDB info stream (stream 1). The signature, age
+/// and GUID must match the RSDS debug directory of the PE file. Editing the
+/// fields changes nothing in the stream directory until "flush" is called.
+/// https://llvm.org/docs/PDB/PdbStream.html
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PdbInfoStream {
+    pub version: u32,
+    /// link.exe uses a timestamp.
+    pub signature: u32,
+    /// Also stored in the DBI header, which keeps the old age until "flush".
+    pub age: u32,
+    pub guid: [u8; 16],
+    /// Maps names like "/names" to stream indices.
+    pub named_stream_map: NamedStreamMap,
+    pub features: Vec<PdbFeature>,
+}
+
+impl PdbInfoStream {
+    /// Parse the PDB info stream.
+    pub fn new(bytes: &[u8]) -> Result<Self, Error> {
+        let mut offset = 0;
+        let read_u32 = |offset: &mut usize| {
+            bytes
+                .gread::<u32>(offset)
+                .map_err(|_| Error::TruncatedStream {
+                    index: PDB_INFO_STREAM_INDEX,
+                    offset: *offset,
+                })
+        };
+        let version = read_u32(&mut offset)?;
+        let signature = read_u32(&mut offset)?;
+        let age = read_u32(&mut offset)?;
+        let guid = bytes
+            .get(offset..offset + 16)
+            .and_then(|guid| guid.try_into().ok())
+            .ok_or(Error::TruncatedStream {
+                index: PDB_INFO_STREAM_INDEX,
+                offset,
+            })?;
+        offset += 16;
+        let named_stream_map = NamedStreamMap::read(bytes, &mut offset)?;
+        let mut features = Vec::new();
+        while offset < bytes.len() {
+            features.push(PdbFeature::from_u32(read_u32(&mut offset)?));
+        }
+        Ok(Self {
+            version,
+            signature,
+            age,
+            guid,
+            named_stream_map,
+            features,
+        })
+    }
+    /// Read the PDB info stream of a stream directory.
+    pub fn from_stream_directory(dir: &StreamDirectory) -> Result<Self, Error> {
+        let stream = dir.stream(PDB_INFO_STREAM_INDEX)?;
+        if stream.is_nil() {
+            return Err(Error::MissingStream(PDB_INFO_STREAM_INDEX));
+        }
+        Self::new(stream.view.as_slice())
+    }
+    /// Serialize the PDB info stream.
+    pub fn to_vec(&self) -> Result<Vec<u8>, Error> {
+        let named_stream_map = self.named_stream_map.to_vec()?;
+        let mut bytes = vec![0u8; 0x1C + named_stream_map.len() + self.features.len() * 4];
+        let mut offset = 0;
+        bytes.gwrite::<u32>(self.version, &mut offset)?;
+        bytes.gwrite::<u32>(self.signature, &mut offset)?;
+        bytes.gwrite::<u32>(self.age, &mut offset)?;
+        bytes[offset..offset + 16].copy_from_slice(&self.guid);
+        offset += 16;
+        bytes[offset..offset + named_stream_map.len()].copy_from_slice(&named_stream_map);
+        offset += named_stream_map.len();
+        for feature in self.features.iter() {
+            bytes.gwrite::<u32>(feature.to_u32(), &mut offset)?;
+        }
+        Ok(bytes)
+    }
+    /// Does the PDB have this feature?
+    pub fn has_feature(&self, feature: PdbFeature) -> bool {
+        self.features.contains(&feature)
+    }
+    /// Write the PDB info stream back into the directory. The age of the DBI
+    /// stream is set to the same age, PDBs without a DBI stream are fine.
+    pub fn flush(&self, dir: &mut StreamDirectory) -> Result<(), Error> {
+        dir.replace_stream(PDB_INFO_STREAM_INDEX, self.to_vec()?)?;
+        let Ok(stream) = dir.stream(DBI_STREAM_INDEX) else {
+            return Ok(());
+        };
+        let mut dbi = DbiStream::new(stream.clone());
+        match dbi.header_mut() {
+            Ok(mut header) => header.set_age(self.age),
+            Err(Error::MissingDbiStream) => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        dir.replace_stream(DBI_STREAM_INDEX, dbi.stream.view.bytes)
+    }
+}
@@ -0,0 +1,205 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::{fmt, io};
+
+use crate::{
+    directory::{write_directory, StreamDirectory, INVALID_STREAM_SIZE},
+    error::Error,
+    msf::{
+        is_valid_page_size, page_offset, BigMsf, MsfBigHeaderMut, PageNumber, BLOCK_MAP_OFFSET,
+        MAGIC,
+    },
+};
+use scroll::Pwrite;
+
+/// Number of pages before the first stream: the header page and both FPM pages.
+const FIRST_STREAM_PAGE: PageNumber = 3;
+
+/// Exposes the streams of a stream directory as a "pdb::Source", so pdb-rs can
+/// read edits which have not been flushed into an MSF file yet.
+///
+/// The streams are not copied into a new MSF file. Instead pdb-rs reads from a
+/// virtual MSF file where every stream is laid out contiguously after the header
+/// and the FPM, followed by the directory and the block map. Only the header, the
+/// directory and the block map exist in memory, reads of stream pages are served
+/// from the views of the directory.
+pub struct PdbSource {
+    /// Page size of the virtual MSF file.
+    page_size: u32,
+    /// The streams, exactly as they are in the directory.
+    dir: StreamDirectory,
+    /// First virtual page of every stream.
+    first_pages: Vec<PageNumber>,
+    /// First virtual page of the directory, the block map follows it.
+    meta_page: PageNumber,
+    /// The header page.
+    header: Vec<u8>,
+    /// Pages of the directory followed by the pages of the block map.
+    meta: Vec<u8>,
+}
+
+impl fmt::Debug for PdbSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "PdbSource({} streams, {} byte pages)",
+            self.dir.streams.len(),
+            self.page_size
+        )
+    }
+}
+
+/// Bytes handed to pdb-rs for a view.
+#[derive(Debug)]
+struct PdbSourceView {
+    bytes: Vec<u8>,
+}
+
+impl pdb::SourceView<'_> for PdbSourceView {
+    fn as_slice(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl PdbSource {
+    /// Lay out the streams of the directory in a virtual MSF file.
+    pub fn new(page_size: u32, dir: StreamDirectory) -> Result<Self, Error> {
+        if !is_valid_page_size(page_size) {
+            return Err(Error::BadPageSize(page_size));
+        }
+        let pages_needed_to_store = |bytes: usize| bytes.div_ceil(page_size as usize) as u32;
+        let mut first_pages = Vec::with_capacity(dir.streams.len());
+        let mut next_page = FIRST_STREAM_PAGE;
+        for stream in dir.streams.iter() {
+            first_pages.push(next_page);
+            next_page += pages_needed_to_store(stream.view.bytes.len());
+        }
+        let pfns = dir
+            .streams
+            .iter()
+            .zip(first_pages.iter())
+            .map(|(stream, first_page)| {
+                (*first_page..*first_page + pages_needed_to_store(stream.view.bytes.len()))
+                    .collect::<Vec<PageNumber>>()
+            })
+            .collect::<Vec<Vec<PageNumber>>>();
+        // Same layout of the directory as "StreamDirectory::flush".
+        let stream_directory_size =
+            4 + dir.streams.len() * 4 + pfns.iter().map(|pfns| pfns.len() * 4).sum::<usize>();
+        let mut meta = vec![0u8; stream_directory_size];
+        write_directory(
+            &mut meta,
+            dir.streams.iter().zip(pfns.iter()).map(|(stream, pfns)| {
+                if stream.is_nil() {
+                    (INVALID_STREAM_SIZE, pfns.as_slice())
+                } else {
+                    (stream.view.bytes.len() as u32, pfns.as_slice())
+                }
+            }),
+        )?;
+        let meta_page = next_page;
+        let directory_pages = pages_needed_to_store(stream_directory_size);
+        let block_map_pages = pages_needed_to_store(directory_pages as usize * 4);
+        meta.resize(page_offset(directory_pages, page_size) as usize, 0);
+        for pfn in meta_page..meta_page + directory_pages {
+            meta.extend_from_slice(&pfn.to_le_bytes());
+        }
+        meta.resize(
+            page_offset(directory_pages + block_map_pages, page_size) as usize,
+            0,
+        );
+        // The header page with the block map array.
+        let mut header = vec![0u8; page_size as usize];
+        let num_pages = meta_page + directory_pages + block_map_pages;
+        {
+            let mut overlay =
+                MsfBigHeaderMut::new(&mut header).ok_or(Error::TruncatedHeader("MSF"))?;
+            overlay.set_magic(MAGIC.try_into().map_err(|_| Error::BadMagic)?);
+            overlay.set_page_size(page_size);
+            overlay.set_free_page_map(1);
+            overlay.set_num_pages(num_pages);
+            overlay.set_stream_dir_size(stream_directory_size as u32);
+            if block_map_pages > overlay.max_block_map_pages() {
+                return Err(Error::DirectoryTooLarge {
+                    size: stream_directory_size as u64,
+                });
+            }
+        }
+        let mut offset = BLOCK_MAP_OFFSET;
+        for pfn in meta_page + directory_pages..num_pages {
+            header.gwrite::<u32>(pfn, &mut offset)?;
+        }
+        Ok(Self {
+            page_size,
+            dir,
+            first_pages,
+            meta_page,
+            header,
+            meta,
+        })
+    }
+    /// Get the directory back, with every edit that was made before.
+    pub fn into_stream_directory(self) -> StreamDirectory {
+        self.dir
+    }
+    /// Fill "buff" with the bytes of the virtual file at "offset".
+    fn read(&self, mut offset: u64, mut buff: &mut [u8]) {
+        let page_size = self.page_size as u64;
+        while !buff.is_empty() {
+            let pfn = (offset / page_size) as PageNumber;
+            let page_start = offset % page_size;
+            let len = std::cmp::min(buff.len() as u64, page_size - page_start) as usize;
+            let (chunk, rest) = buff.split_at_mut(len);
+            chunk.fill(0);
+            let bytes: &[u8] = if pfn == 0 {
+                &self.header
+            } else if pfn < FIRST_STREAM_PAGE {
+                // Nothing reads the FPM, every page is free.
+                chunk.fill(0xFF);
+                &[]
+            } else if pfn >= self.meta_page {
+                let meta_start = page_offset(pfn - self.meta_page, self.page_size) as usize;
+                self.meta.get(meta_start..).unwrap_or(&[])
+            } else {
+                // Streams are laid out in order, nil and empty streams have no pages.
+                let index = self.first_pages.partition_point(|first| *first <= pfn) - 1;
+                let stream_start = page_offset(pfn - self.first_pages[index], self.page_size);
+                &self.dir.streams[index].view.bytes[stream_start as usize..]
+            };
+            let bytes = bytes
+                .get(page_start as usize..)
+                .map(|bytes| &bytes[..std::cmp::min(bytes.len(), len)])
+                .unwrap_or(&[]);
+            chunk[..bytes.len()].copy_from_slice(bytes);
+            offset += len as u64;
+            buff = rest;
+        }
+    }
+}
+
+impl<'s> pdb::Source<'s> for PdbSource {
+    fn view(
+        &mut self,
+        slices: &[pdb::SourceSlice],
+    ) -> Result<Box<dyn pdb::SourceView<'s>>, io::Error> {
+        let len = slices.iter().map(|slice| slice.size).sum::<usize>();
+        let mut bytes = vec![0u8; len];
+        let mut current_offset = 0;
+        for slice in slices {
+            self.read(
+                slice.offset,
+                &mut bytes[current_offset..current_offset + slice.size],
+            );
+            current_offset += slice.size;
+        }
+        Ok(Box::new(PdbSourceView { bytes }))
+    }
+}
+
+impl BigMsf {
+    /// Expose the streams of this MSF as a "pdb::Source".
+    pub fn to_pdb_source(&self) -> Result<PdbSource, Error> {
+        let page_size = self.header()?.get_page_size();
+        PdbSource::new(page_size, self.get_stream_directory()?)
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use crate::error::Error;
+use scroll::{Pread, Pwrite};
+
+/// Size of an IMAGE_SECTION_HEADER.
+pub const SECTION_HEADER_SIZE: usize = 0x28;
+/// Size of a section map entry.
+pub const SECTION_MAP_ENTRY_SIZE: usize = 0x14;
+
+/// Section characteristics the section map flags are derived from.
+pub const IMAGE_SCN_MEM_16BIT: u32 = 0x00020000;
+pub const IMAGE_SCN_MEM_EXECUTE: u32 = 0x20000000;
+pub const IMAGE_SCN_MEM_READ: u32 = 0x40000000;
+pub const IMAGE_SCN_MEM_WRITE: u32 = 0x80000000;
+
+/// Flags of a section map entry.
+pub const SECTION_MAP_READ: u16 = 1 << 0;
+pub const SECTION_MAP_WRITE: u16 = 1 << 1;
+pub const SECTION_MAP_EXECUTE: u16 = 1 << 2;
+pub const SECTION_MAP_ADDRESS_IS_32_BIT: u16 = 1 << 3;
+pub const SECTION_MAP_IS_SELECTOR: u16 = 1 << 8;
+pub const SECTION_MAP_IS_ABSOLUTE_ADDRESS: u16 = 1 << 9;
+pub const SECTION_MAP_IS_GROUP: u16 = 1 << 10;
+
+/// An IMAGE_SECTION_HEADER of the section headers streams.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SectionHeader {
+    pub name: [u8; 8],
+    pub virtual_size: u32,
+    pub virtual_address: u32,
+    pub size_of_raw_data: u32,
+    pub pointer_to_raw_data: u32,
+    pub pointer_to_relocations: u32,
+    pub pointer_to_linenumbers: u32,
+    pub number_of_relocations: u16,
+    pub number_of_linenumbers: u16,
+    pub characteristics: u32,
+}
+
+impl SectionHeader {
+    /// Parse every section header of a section headers stream. The stream must
+    /// hold whole section headers.
+    pub fn read_all(bytes: &[u8]) -> Result<Vec<Self>, Error> {
+        if !bytes.len().is_multiple_of(SECTION_HEADER_SIZE) {
+            return Err(Error::OutOfBounds {
+                offset: (bytes.len() - bytes.len() % SECTION_HEADER_SIZE) as u64,
+                size: SECTION_HEADER_SIZE as u64,
+            });
+        }
+        let mut section_headers = Vec::with_capacity(bytes.len() / SECTION_HEADER_SIZE);
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let mut name = [0u8; 8];
+            name.copy_from_slice(&bytes[offset..offset + 8]);
+            offset += 8;
+            section_headers.push(Self {
+                name,
+                virtual_size: bytes.gread::<u32>(&mut offset)?,
+                virtual_address: bytes.gread::<u32>(&mut offset)?,
+                size_of_raw_data: bytes.gread::<u32>(&mut offset)?,
+                pointer_to_raw_data: bytes.gread::<u32>(&mut offset)?,
+                pointer_to_relocations: bytes.gread::<u32>(&mut offset)?,
+                pointer_to_linenumbers: bytes.gread::<u32>(&mut offset)?,
+                number_of_relocations: bytes.gread::<u16>(&mut offset)?,
+                number_of_linenumbers: bytes.gread::<u16>(&mut offset)?,
+                characteristics: bytes.gread::<u32>(&mut offset)?,
+            });
+        }
+        Ok(section_headers)
+    }
+}
+
+/// Maps a segment (frame) of a symbol's address to a section.
+/// https://llvm.org/docs/PDB/DbiStream.html#section-map-substream
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SectionMapEntry {
+    /// SECTION_MAP_* flags.
+    pub flags: u16,
+    /// Logical overlay number.
+    pub ovl: u16,
+    /// Group index into the descriptor array.
+    pub group: u16,
+    /// One based index of the section.
+    pub frame: u16,
+    /// Index of the section name in the string table, 0xFFFF for none.
+    pub section_name: u16,
+    /// Index of the class name in the string table, 0xFFFF for none.
+    pub class_name: u16,
+    /// Byte offset of the logical segment within the section.
+    pub offset: u32,
+    /// Byte count of the segment or group.
+    pub section_length: u32,
+}
+
+/// The section map substream of the DBI stream.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SectionMap {
+    /// Number of logical segments, MSVC writes the number of entries.
+    pub log_count: u16,
+    pub entries: Vec<SectionMapEntry>,
+}
+
+impl SectionMap {
+    /// Parse the section map substream.
+    pub fn new(bytes: &[u8]) -> Result<Self, Error> {
+        let mut offset = 0;
+        let count = bytes.gread::<u16>(&mut offset)? as usize;
+        let log_count = bytes.gread::<u16>(&mut offset)?;
+        if bytes.len() < 4 + count * SECTION_MAP_ENTRY_SIZE {
+            return Err(Error::OutOfBounds {
+                offset: 4,
+                size: (count * SECTION_MAP_ENTRY_SIZE) as u64,
+            });
+        }
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            entries.push(SectionMapEntry {
+                flags: bytes.gread::<u16>(&mut offset)?,
+                ovl: bytes.gread::<u16>(&mut offset)?,
+                group: bytes.gread::<u16>(&mut offset)?,
+                frame: bytes.gread::<u16>(&mut offset)?,
+                section_name: bytes.gread::<u16>(&mut offset)?,
+                class_name: bytes.gread::<u16>(&mut offset)?,
+                offset: bytes.gread::<u32>(&mut offset)?,
+                section_length: bytes.gread::<u32>(&mut offset)?,
+            });
+        }
+        Ok(Self { log_count, entries })
+    }
+    /// Build the section map MSVC writes for these section headers. Every
+    /// section gets an entry, followed by one for absolute addresses.
+    pub fn from_section_headers(section_headers: &[SectionHeader]) -> Self {
+        let mut entries = section_headers
+            .iter()
+            .enumerate()
+            .map(|(index, section_header)| {
+                let characteristics = section_header.characteristics;
+                let mut flags = SECTION_MAP_IS_SELECTOR;
+                for (characteristic, flag) in [
+                    (IMAGE_SCN_MEM_READ, SECTION_MAP_READ),
+                    (IMAGE_SCN_MEM_WRITE, SECTION_MAP_WRITE),
+                    (IMAGE_SCN_MEM_EXECUTE, SECTION_MAP_EXECUTE),
+                ] {
+                    if characteristics & characteristic != 0 {
+                        flags |= flag;
+                    }
+                }
+                if characteristics & IMAGE_SCN_MEM_16BIT == 0 {
+                    flags |= SECTION_MAP_ADDRESS_IS_32_BIT;
+                }
+                SectionMapEntry {
+                    flags,
+                    frame: (index + 1) as u16,
+                    section_name: u16::MAX,
+                    class_name: u16::MAX,
+                    section_length: section_header.virtual_size,
+                    ..Default::default()
+                }
+            })
+            .collect::<Vec<SectionMapEntry>>();
+        entries.push(SectionMapEntry {
+            flags: SECTION_MAP_ADDRESS_IS_32_BIT | SECTION_MAP_IS_ABSOLUTE_ADDRESS,
+            section_name: u16::MAX,
+            class_name: u16::MAX,
+            section_length: u32::MAX,
+            ..Default::default()
+        });
+        Self {
+            log_count: entries.len() as u16,
+            entries,
+        }
+    }
+    /// Serialize the section map substream.
+    pub fn to_vec(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = vec![0u8; 4 + self.entries.len() * SECTION_MAP_ENTRY_SIZE];
+        let mut offset = 0;
+        bytes.gwrite::<u16>(self.entries.len() as u16, &mut offset)?;
+        bytes.gwrite::<u16>(self.log_count, &mut offset)?;
+        for entry in self.entries.iter() {
+            bytes.gwrite::<u16>(entry.flags, &mut offset)?;
+            bytes.gwrite::<u16>(entry.ovl, &mut offset)?;
+            bytes.gwrite::<u16>(entry.group, &mut offset)?;
+            bytes.gwrite::<u16>(entry.frame, &mut offset)?;
+            bytes.gwrite::<u16>(entry.section_name, &mut offset)?;
+            bytes.gwrite::<u16>(entry.class_name, &mut offset)?;
+            bytes.gwrite::<u32>(entry.offset, &mut offset)?;
+            bytes.gwrite::<u32>(entry.section_length, &mut offset)?;
+        }
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,161 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use crate::{
+    directory::{Stream, StreamDirectory, INVALID_STREAM_SIZE},
+    error::Error,
+    msf::{is_valid_small_page_size, BigMsf},
+    pagelist::PageList,
+    struct_overlay_both,
+    view::SourceView,
+};
+use scroll::Pread;
+use static_assertions::const_assert;
+
+/// Magic bytes of the PDB file format 2.0
+pub const SMALL_MAGIC: &[u8] = b"Microsoft C/C++ program database 2.00\r\n\x1a\x4a\x47\x00\x00";
+/// Offset of the array of stream directory pages inside of the MSF header page.
+pub const SMALL_DIRECTORY_PAGES_OFFSET: usize = 0x3C;
+
+// https://github.com/microsoft/microsoft-pdb/blob/master/PDB/msf/msf.cpp
+// struct MSF_HDR {
+//     char szMagic[0x2C];
+//     CB cbPg;
+//     PN pnFpm;
+//     PN pnMac;
+//     SI_PERSIST siSt;
+//     PN mpspnpn[cpnMaxForCb(cbDbMax)];
+// };
+struct_overlay_both!((pub MsfSmallHeader, pub MsfSmallHeaderMut) {
+    // Must be equal to "Microsoft C/C++ program database 2.00\r\n" followed by the bytes 1A 4A 47 00 00.
+    [0x00] magic: [u8; 44],
+    // The page size of the internal file system.
+    [0x2C] page_size: u32,
+    // The page of the free page map, page numbers are only 16 bits in small MSF files.
+    [0x30] free_page_map: u16,
+    // The total number of pages in the file.
+    [0x32] num_pages: u16,
+    // The size of the stream directory in bytes. The page numbers of the
+    // stream directory follow this header directly, there is no block map.
+    [0x34] stream_dir_size: u32,
+    [0x38] unknown: u32,
+});
+const_assert!(MsfSmallHeader::size() == 0x3C);
+
+impl<'a> MsfSmallHeader<'a> {
+    /// Validates the magic bytes in the header.
+    pub fn from(bytes: &'a [u8]) -> Result<Self, Error> {
+        let header = Self::new(bytes).ok_or(Error::TruncatedHeader("small MSF"))?;
+        if header.get_magic() == SMALL_MAGIC {
+            Ok(header)
+        } else {
+            Err(Error::BadMagic)
+        }
+    }
+    /// How many pages are required to store N amount of bytes?
+    #[inline(always)]
+    pub fn pages_needed_to_store(&self, bytes: u32) -> u32 {
+        bytes.div_ceil(self.get_page_size())
+    }
+}
+
+/// High level abstraction of a PDB 2.0 (small MSF) file. These can only be read,
+/// use "to_big_msf" to upgrade one to a PDB 7.0 file which can be manipulated.
+#[derive(Debug, Default)]
+pub struct SmallMsf {
+    /// Internal back buffer of the MSF file.
+    pub bytes: Vec<u8>,
+}
+
+impl SmallMsf {
+    /// Create a new small MSF/PDB given a copy of its bytes.
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes }
+    }
+    /// Get an immutable reference to the MSF header.
+    #[inline(always)]
+    pub fn header(&self) -> Result<MsfSmallHeader<'_>, Error> {
+        MsfSmallHeader::from(&self.bytes)
+    }
+    /// Find and parse the stream directory, the streams are lifted the same way
+    /// as they are for big MSF files.
+    pub fn get_stream_directory(&self) -> Result<StreamDirectory, Error> {
+        let header = self.header()?;
+        let page_size = header.get_page_size();
+        if !is_valid_small_page_size(page_size) {
+            return Err(Error::BadPageSize(page_size));
+        }
+        // Page numbers of the stream directory are stored right after the header.
+        let num_pages = header.pages_needed_to_store(header.get_stream_dir_size());
+        if SMALL_DIRECTORY_PAGES_OFFSET + num_pages as usize * 2 > page_size as usize {
+            return Err(Error::DirectoryTooLarge {
+                size: header.get_stream_dir_size() as u64,
+            });
+        }
+        let mut offset = SMALL_DIRECTORY_PAGES_OFFSET;
+        let mut pages = PageList::new(page_size);
+        for _ in 0..num_pages {
+            pages.push(
+                self.bytes
+                    .gread::<u16>(&mut offset)
+                    .map_err(|_| Error::TruncatedHeader("small MSF"))? as u32,
+            );
+        }
+        let view =
+            SourceView::with_size(&self.bytes, pages, header.get_stream_dir_size() as usize)?;
+        let truncated = |offset: usize| Error::TruncatedDirectory { offset };
+        let buff = view.as_slice();
+        let mut offset = 0;
+        // Read the number of streams, followed by 2 reserved bytes.
+        let num_streams = buff
+            .gread::<u16>(&mut offset)
+            .map_err(|_| truncated(offset))?;
+        offset += 2;
+        let mut streams = Vec::<Stream>::new();
+        // Each stream has its size followed by 4 reserved bytes.
+        for _ in 0..num_streams {
+            streams.push(Stream {
+                original_stream_size: buff
+                    .gread::<u32>(&mut offset)
+                    .map_err(|_| truncated(offset))?,
+                ..Default::default()
+            });
+            offset += 4;
+        }
+        // Read the 16 bit pages for each stream.
+        for stream in streams.iter_mut() {
+            if stream.original_stream_size != INVALID_STREAM_SIZE {
+                let num_pages = header.pages_needed_to_store(stream.original_stream_size);
+                let mut pages = PageList::new(page_size);
+                for _ in 0..num_pages {
+                    pages.push(
+                        buff.gread::<u16>(&mut offset)
+                            .map_err(|_| truncated(offset))? as u32,
+                    );
+                }
+                stream.view.pages = pages;
+            }
+        }
+        let mut dir = StreamDirectory {
+            streams,
+            view,
+            ..Default::default()
+        };
+        dir.check_num_pages(self.bytes.len() as u64 / page_size as u64)?;
+        for (index, stream) in dir.streams.iter_mut().enumerate() {
+            if stream.original_stream_size != INVALID_STREAM_SIZE {
+                stream.view = SourceView::with_size(
+                    &self.bytes,
+                    stream.view.pages.clone(),
+                    stream.original_stream_size as usize,
+                )
+                .map_err(|e| stream.truncated(index, e))?;
+            }
+        }
+        Ok(dir)
+    }
+    /// Upgrade to a PDB 7.0 (big MSF) file with the same page size and streams.
+    pub fn to_big_msf(&self) -> Result<BigMsf, Error> {
+        let header = self.header()?;
+        BigMsf::from_stream_directory(header.get_page_size(), self.get_stream_directory()?)
+    }
+}
@@ -0,0 +1,112 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+};
+
+use crate::error::Error;
+use memmap2::Mmap;
+
+/// Where the pages of an MSF file are read from. Pages are only read when a
+/// view of them is created, so the file never has to be in memory as a whole.
+pub trait PageSource {
+    /// Size of the file in bytes.
+    fn len(&self) -> u64;
+    /// Is the file empty?
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Fill "buff" with the bytes at "offset", reading past the end is an error.
+    fn read_at(&mut self, offset: u64, buff: &mut [u8]) -> Result<(), Error>;
+}
+
+/// Pages of a file which is already in memory, like a "Vec<u8>" or a memory mapping.
+#[derive(Debug, Default, Clone)]
+pub struct BytesSource<T>(pub T);
+
+impl<T: AsRef<[u8]>> PageSource for BytesSource<T> {
+    fn len(&self) -> u64 {
+        self.0.as_ref().len() as u64
+    }
+    fn read_at(&mut self, offset: u64, buff: &mut [u8]) -> Result<(), Error> {
+        let bytes = usize::try_from(offset)
+            .ok()
+            .and_then(|offset| self.0.as_ref().get(offset..offset.checked_add(buff.len())?))
+            .ok_or(Error::OutOfBounds {
+                offset,
+                size: buff.len() as u64,
+            })?;
+        buff.copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// Pages of a memory mapped file.
+pub type MmapSource = BytesSource<Mmap>;
+
+impl MmapSource {
+    /// Map the whole file read only.
+    pub fn map(file: &File) -> Result<Self, Error> {
+        // SAFETY: The mapping is read only, the file must not be truncated while it is mapped.
+        Ok(Self(unsafe { Mmap::map(file)? }))
+    }
+}
+
+/// Pages of anything that can be read and seeked, like a "File".
+#[derive(Debug)]
+pub struct ReadSeekSource<R> {
+    /// The underlying reader.
+    pub reader: R,
+    /// Size of the reader in bytes.
+    len: u64,
+}
+
+impl<R: Read + Seek> ReadSeekSource<R> {
+    /// Create a new source, the size is found by seeking to the end.
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        Ok(Self { reader, len })
+    }
+}
+
+impl<R: Read + Seek> PageSource for ReadSeekSource<R> {
+    fn len(&self) -> u64 {
+        self.len
+    }
+    fn read_at(&mut self, offset: u64, buff: &mut [u8]) -> Result<(), Error> {
+        if offset.saturating_add(buff.len() as u64) > self.len {
+            return Err(Error::OutOfBounds {
+                offset,
+                size: buff.len() as u64,
+            });
+        }
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.reader.read_exact(buff)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BytesSource, PageSource, ReadSeekSource};
+    use std::io::Cursor;
+
+    /// Both sources read the same bytes and refuse to read past the end.
+    #[test]
+    fn read_sources() {
+        let bytes = (0..0x100u32).map(|e| e as u8).collect::<Vec<u8>>();
+        let mut bytes_source = BytesSource(bytes.clone());
+        let mut read_seek_source = ReadSeekSource::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(bytes_source.len(), 0x100);
+        assert_eq!(read_seek_source.len(), 0x100);
+        let mut buff = [0u8; 0x10];
+        bytes_source.read_at(0xF0, &mut buff).unwrap();
+        assert_eq!(buff[0], 0xF0);
+        read_seek_source.read_at(0x20, &mut buff).unwrap();
+        assert_eq!(buff[0xF], 0x2F);
+        assert!(bytes_source.read_at(0xF1, &mut buff).is_err());
+        assert!(read_seek_source.read_at(0xF1, &mut buff).is_err());
+        assert!(bytes_source.read_at(u64::MAX, &mut buff).is_err());
+    }
+}
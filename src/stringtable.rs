@@ -0,0 +1,245 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use crate::{
+    directory::StreamDirectory,
+    error::Error,
+    hash::{hash_string_v1, hash_string_v2},
+    pdbinfo::PdbInfoStream,
+};
+use scroll::{Pread, Pwrite};
+
+/// Signature of the "/names" string table.
+pub const STRING_TABLE_SIGNATURE: u32 = 0xEFFEEFFE;
+/// Strings are hashed with hashStringV1, written by every linker we know of.
+pub const STRING_TABLE_HASH_V1: u32 = 1;
+/// Strings are hashed with hashStringV2.
+pub const STRING_TABLE_HASH_V2: u32 = 2;
+/// Name of the string table in the named stream map.
+pub const STRING_TABLE_NAME: &str = "/names";
+
+/// High level abstraction of the "/names" stream. C13 line info and FPO2
+/// records refer to file names and FrameData programs by their offset in it.
+/// The EC substream of the DBI stream uses the same format.
+/// https://llvm.org/docs/PDB/StringTable.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StringTable {
+    /// STRING_TABLE_HASH_V1 or STRING_TABLE_HASH_V2.
+    pub version: u32,
+    /// Every string followed by a null terminator, offset 0 is the empty string.
+    pub strings: Vec<u8>,
+    /// Offset of the string in every bucket, 0 for empty buckets.
+    pub buckets: Vec<u32>,
+}
+
+impl Default for StringTable {
+    fn default() -> Self {
+        Self {
+            version: STRING_TABLE_HASH_V1,
+            strings: vec![0],
+            buckets: vec![0],
+        }
+    }
+}
+
+impl StringTable {
+    /// Parse a string table.
+    pub fn new(bytes: &[u8]) -> Result<Self, Error> {
+        let mut offset = 0;
+        let read_u32 = |offset: &mut usize| {
+            let start = *offset;
+            bytes.gread::<u32>(offset).map_err(|_| Error::OutOfBounds {
+                offset: start as u64,
+                size: 4,
+            })
+        };
+        let signature = read_u32(&mut offset)?;
+        if signature != STRING_TABLE_SIGNATURE {
+            return Err(Error::BadSignature {
+                structure: "string table",
+                signature,
+            });
+        }
+        let version = read_u32(&mut offset)?;
+        if version != STRING_TABLE_HASH_V1 && version != STRING_TABLE_HASH_V2 {
+            return Err(Error::Unsupported(format!(
+                "string table hash version {version}"
+            )));
+        }
+        let size = read_u32(&mut offset)? as usize;
+        let strings = bytes
+            .get(offset..)
+            .and_then(|bytes| bytes.get(..size))
+            .ok_or(Error::OutOfBounds {
+                offset: offset as u64,
+                size: size as u64,
+            })?
+            .to_vec();
+        offset += size;
+        let capacity = read_u32(&mut offset)?;
+        let buckets_size = capacity as u64 * 4;
+        if buckets_size > (bytes.len() - offset) as u64 {
+            return Err(Error::OutOfBounds {
+                offset: offset as u64,
+                size: buckets_size,
+            });
+        }
+        let mut buckets = Vec::with_capacity(capacity as usize);
+        for _ in 0..capacity {
+            buckets.push(read_u32(&mut offset)?);
+        }
+        let count = read_u32(&mut offset)?;
+        let table = Self {
+            version,
+            strings,
+            buckets,
+        };
+        // Every bucket must point at a string, there is no use for the count
+        // other than checking it.
+        let present = table.buckets.iter().filter(|key| **key != 0);
+        if present.clone().count() != count as usize
+            || present
+                .clone()
+                .any(|key| *key as usize >= table.strings.len())
+        {
+            return Err(Error::BadHashTable {
+                size: count,
+                capacity,
+            });
+        }
+        Ok(table)
+    }
+    /// Read the string table the named stream map points at.
+    pub fn from_stream_directory(dir: &StreamDirectory) -> Result<Self, Error> {
+        let info = PdbInfoStream::from_stream_directory(dir)?;
+        let index = info
+            .named_stream_map
+            .get(STRING_TABLE_NAME)
+            .ok_or(Error::MissingNamedStream(STRING_TABLE_NAME.into()))?;
+        Self::new(dir.stream(index as usize)?.view.as_slice())
+    }
+    /// Serialize the string table.
+    pub fn to_vec(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = vec![0u8; 0x14 + self.strings.len() + self.buckets.len() * 4];
+        let mut offset = 0;
+        bytes.gwrite::<u32>(STRING_TABLE_SIGNATURE, &mut offset)?;
+        bytes.gwrite::<u32>(self.version, &mut offset)?;
+        bytes.gwrite::<u32>(self.strings.len() as u32, &mut offset)?;
+        bytes[offset..offset + self.strings.len()].copy_from_slice(&self.strings);
+        offset += self.strings.len();
+        bytes.gwrite::<u32>(self.buckets.len() as u32, &mut offset)?;
+        for key in self.buckets.iter() {
+            bytes.gwrite::<u32>(*key, &mut offset)?;
+        }
+        bytes.gwrite::<u32>(self.len() as u32, &mut offset)?;
+        Ok(bytes)
+    }
+    /// Write the string table back into the stream the named stream map points
+    /// at. PDBs without one get a new stream named "/names".
+    pub fn flush(&self, dir: &mut StreamDirectory) -> Result<(), Error> {
+        let mut info = PdbInfoStream::from_stream_directory(dir)?;
+        match info.named_stream_map.get(STRING_TABLE_NAME) {
+            Some(index) => dir.replace_stream(index as usize, self.to_vec()?),
+            None => {
+                let index = dir.add_stream(self.to_vec()?);
+                info.named_stream_map
+                    .insert(STRING_TABLE_NAME, index as u32);
+                info.flush(dir)
+            }
+        }
+    }
+    /// Number of strings in the hash table, the empty string is not in it.
+    pub fn len(&self) -> usize {
+        self.buckets.iter().filter(|key| **key != 0).count()
+    }
+    /// Does the hash table have no strings?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The null terminated bytes at "offset", offsets come from C13 line info
+    /// and FPO2 records. Paths are in the ANSI code page, not UTF-8.
+    pub fn get(&self, offset: u32) -> Option<&[u8]> {
+        let string = self.strings.get(offset as usize..)?;
+        let len = string.iter().position(|byte| *byte == 0)?;
+        Some(&string[..len])
+    }
+    /// Same as "get" but only for strings which are UTF-8.
+    pub fn get_str(&self, offset: u32) -> Option<&str> {
+        std::str::from_utf8(self.get(offset)?).ok()
+    }
+    /// The offset of "string", found through the hash table.
+    pub fn find(&self, string: &[u8]) -> Option<u32> {
+        match self.bucket_of(string) {
+            Ok(bucket) => Some(self.buckets[bucket]),
+            // Offset 0 is the empty string even when it is not hashed.
+            Err(_) if string.is_empty() && !self.strings.is_empty() => Some(0),
+            Err(_) => None,
+        }
+    }
+    /// Every (offset, string) in the hash table in offset order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &[u8])> {
+        let mut keys = self
+            .buckets
+            .iter()
+            .copied()
+            .filter(|key| *key != 0)
+            .collect::<Vec<u32>>();
+        keys.sort_unstable();
+        keys.into_iter()
+            .filter_map(|key| Some((key, self.get(key)?)))
+    }
+    /// Add a string and return its offset. Strings already in the table keep
+    /// their offset. The hash table doubles once it is two thirds full.
+    pub fn insert(&mut self, string: &[u8]) -> u32 {
+        if let Some(offset) = self.find(string) {
+            return offset;
+        }
+        if self.strings.is_empty() {
+            self.strings.push(0);
+        }
+        let key = self.strings.len() as u32;
+        self.strings.extend_from_slice(string);
+        self.strings.push(0);
+        if (self.len() + 1) * 3 > self.buckets.len() * 2 {
+            let capacity = (self.buckets.len() * 2).max(2);
+            let keys = std::mem::replace(&mut self.buckets, vec![0; capacity]);
+            for key in keys.into_iter().filter(|key| *key != 0) {
+                self.place(key);
+            }
+        }
+        self.place(key);
+        key
+    }
+    /// Hash of a string with the hash function of this table's version.
+    fn hash(&self, string: &[u8]) -> u32 {
+        match self.version {
+            STRING_TABLE_HASH_V2 => hash_string_v2(string),
+            _ => hash_string_v1(string),
+        }
+    }
+    /// The bucket of "string", or the first empty bucket it can be placed in.
+    fn bucket_of(&self, string: &[u8]) -> Result<usize, Option<usize>> {
+        if self.buckets.is_empty() {
+            return Err(None);
+        }
+        let start = self.hash(string) as usize % self.buckets.len();
+        let mut bucket = start;
+        loop {
+            match self.buckets[bucket] {
+                0 => return Err(Some(bucket)),
+                key if self.get(key) == Some(string) => return Ok(bucket),
+                _ => {}
+            }
+            bucket = (bucket + 1) % self.buckets.len();
+            if bucket == start {
+                return Err(None);
+            }
+        }
+    }
+    /// Put the string at "key" in the first empty bucket of its probe sequence.
+    fn place(&mut self, key: u32) {
+        let string = self.get(key).unwrap_or_default().to_vec();
+        if let Err(Some(bucket)) = self.bucket_of(&string) {
+            self.buckets[bucket] = key;
+        }
+    }
+}
@@ -0,0 +1,30 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use crate::struct_overlay_both;
+use static_assertions::const_assert;
+
+/// Version of the TPI and IPI streams written by VC 8.0 and newer.
+pub const TPI_VERSION_V80: u32 = 20040203;
+/// The first type index which is not a simple (built in) type.
+pub const TPI_FIRST_TYPE_INDEX: u32 = 0x1000;
+
+// https://llvm.org/docs/PDB/TpiStream.html#stream-header
+// This header is shared by the TPI and IPI streams.
+struct_overlay_both!((pub TpiStreamHeaderOverlay, pub TpiStreamHeaderOverlayMut) {
+    [0x00] version: u32,
+    [0x04] header_size: u32,
+    [0x08] type_index_begin: u32,
+    [0x0C] type_index_end: u32,
+    [0x10] type_record_bytes: u32,
+    [0x14] hash_stream_index: u16,
+    [0x16] hash_aux_stream_index: u16,
+    [0x18] hash_key_size: u32,
+    [0x1C] num_hash_buckets: u32,
+    [0x20] hash_value_buffer_offset: u32,
+    [0x24] hash_value_buffer_length: u32,
+    [0x28] index_offset_buffer_offset: u32,
+    [0x2C] index_offset_buffer_length: u32,
+    [0x30] hash_adj_buffer_offset: u32,
+    [0x34] hash_adj_buffer_length: u32,
+});
+const_assert!(TpiStreamHeaderOverlay::size() == 0x38);
@@ -0,0 +1,254 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::collections::BTreeMap;
+
+use crate::{
+    directory::INVALID_STREAM_SIZE,
+    error::Error,
+    fpm::{is_fpm_page, FreePageMap},
+    msf::{is_valid_page_size, page_offset, BigMsf, MsfBigHeader, PageNumber, BLOCK_MAP_OFFSET},
+};
+use scroll::Pread;
+
+/// What a page of the MSF file is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageOwner {
+    /// Page 0, the MSF header.
+    Header,
+    /// One of the two FPM pages of an interval.
+    FreePageMap,
+    /// A page of the stream block map, it holds the page numbers of the directory.
+    BlockMap,
+    /// A page of the stream directory.
+    Directory,
+    /// A page of the stream with this index.
+    Stream(usize),
+}
+
+/// A single problem found by "BigMsf::verify".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+    /// The header has bad magic, an invalid page size or an invalid FPM page.
+    /// Nothing else is checked when this is found.
+    BadHeader(String),
+    /// The file is not "num_pages * page_size" bytes long.
+    FileLengthMismatch { expected: u64, actual: u64 },
+    /// A page number is not smaller than "num_pages".
+    PageOutOfRange { owner: PageOwner, pfn: PageNumber },
+    /// A page is referenced twice.
+    DuplicatePage {
+        pfn: PageNumber,
+        first: PageOwner,
+        second: PageOwner,
+    },
+    /// A page is in use but the FPM says it is free.
+    UsedPageMarkedFree { pfn: PageNumber, owner: PageOwner },
+    /// Nothing references the page but the FPM says it is in use.
+    FreePageMarkedUsed { pfn: PageNumber },
+    /// The directory size in the header does not match what the directory describes.
+    DirectorySizeMismatch { expected: u32, actual: u32 },
+    /// The stream directory could not be read.
+    BadDirectory(String),
+    /// Writing the stream directory and parsing it again failed.
+    RoundTripFailed(String),
+    /// A stream has different contents after writing and parsing it again.
+    RoundTripMismatch { stream: usize },
+}
+
+/// Every page that is referenced and what it is referenced by.
+#[derive(Debug, Default)]
+struct PageOwners {
+    num_pages: u32,
+    owners: BTreeMap<PageNumber, PageOwner>,
+}
+
+impl PageOwners {
+    /// Record that "owner" uses the page.
+    fn claim(&mut self, owner: PageOwner, pfn: PageNumber, findings: &mut Vec<Finding>) {
+        if pfn >= self.num_pages {
+            findings.push(Finding::PageOutOfRange { owner, pfn });
+            return;
+        }
+        if let Some(first) = self.owners.get(&pfn) {
+            findings.push(Finding::DuplicatePage {
+                pfn,
+                first: *first,
+                second: owner,
+            });
+        } else {
+            self.owners.insert(pfn, owner);
+        }
+    }
+}
+
+/// Read the pages of a view, returns None if any of them is not in the buffer.
+fn read_pages(buff: &[u8], pfns: &[PageNumber], page_size: u32, size: usize) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(pfns.len() * page_size as usize);
+    for pfn in pfns {
+        let page_start = usize::try_from(page_offset(*pfn, page_size)).ok()?;
+        bytes.extend_from_slice(buff.get(page_start..page_start + page_size as usize)?);
+    }
+    bytes.truncate(size);
+    Some(bytes)
+}
+
+impl BigMsf {
+    /// Check the MSF for inconsistencies without changing it. Returns every
+    /// problem that was found, an empty list means the MSF is consistent.
+    ///
+    /// Pages of stream 0 (the old stream directory) may be free or used since
+    /// link.exe marks them as free.
+    pub fn verify(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        let header = match MsfBigHeader::from(&self.bytes) {
+            Ok(header) => header,
+            Err(e) => return vec![Finding::BadHeader(e.to_string())],
+        };
+        let page_size = header.get_page_size();
+        if !is_valid_page_size(page_size) {
+            return vec![Finding::BadHeader(
+                Error::BadPageSize(page_size).to_string(),
+            )];
+        }
+        if !matches!(header.get_free_page_map(), 1 | 2) {
+            return vec![Finding::BadHeader(format!(
+                "Invalid FPM page {}!",
+                header.get_free_page_map()
+            ))];
+        }
+        if header.file_size() != self.bytes.len() as u64 {
+            findings.push(Finding::FileLengthMismatch {
+                expected: header.file_size(),
+                actual: self.bytes.len() as u64,
+            });
+        }
+        // Pages the header claims but the file does not have do not exist.
+        let num_pages = std::cmp::min(
+            header.get_num_pages() as u64,
+            self.bytes.len() as u64 / page_size as u64,
+        ) as u32;
+        let mut owners = PageOwners {
+            num_pages,
+            ..Default::default()
+        };
+        owners.claim(PageOwner::Header, 0, &mut findings);
+        for pfn in (0..num_pages).filter(|pfn| is_fpm_page(*pfn, page_size)) {
+            owners.claim(PageOwner::FreePageMap, pfn, &mut findings);
+        }
+        // Without the directory it is unknown which pages are in use.
+        if let Err(finding) = self.verify_directory(&header, &mut owners, &mut findings) {
+            findings.push(finding);
+            return findings;
+        }
+        // Compare the FPM to the pages which are actually referenced.
+        let fpm = FreePageMap::read(&self.bytes, &header);
+        for pfn in 0..num_pages {
+            match owners.owners.get(&pfn) {
+                Some(PageOwner::Stream(0)) => {}
+                Some(owner) if fpm.is_free(pfn) => {
+                    findings.push(Finding::UsedPageMarkedFree { pfn, owner: *owner })
+                }
+                None if !fpm.is_free(pfn) => findings.push(Finding::FreePageMarkedUsed { pfn }),
+                _ => {}
+            }
+        }
+        if findings.is_empty() {
+            if let Err(finding) = self.verify_round_trip() {
+                findings.push(finding);
+            }
+        }
+        findings
+    }
+    /// Claim the pages of the block map, the directory and every stream.
+    fn verify_directory(
+        &self,
+        header: &MsfBigHeader<'_>,
+        owners: &mut PageOwners,
+        findings: &mut Vec<Finding>,
+    ) -> Result<(), Finding> {
+        let page_size = header.get_page_size();
+        let bad_directory = |e: scroll::Error| Finding::BadDirectory(e.to_string());
+        let dir_size = header.get_stream_dir_size();
+        let num_dir_pages = header.pages_needed_to_store(dir_size);
+        let num_block_map_pages = header.pages_needed_to_store(num_dir_pages * 4);
+        if num_block_map_pages > header.max_block_map_pages() {
+            return Err(Finding::BadDirectory(
+                Error::DirectoryTooLarge {
+                    size: dir_size as u64,
+                }
+                .to_string(),
+            ));
+        }
+        let mut offset = BLOCK_MAP_OFFSET;
+        let mut block_map_pages = Vec::new();
+        for _ in 0..num_block_map_pages {
+            let pfn = self
+                .bytes
+                .gread::<u32>(&mut offset)
+                .map_err(bad_directory)?;
+            owners.claim(PageOwner::BlockMap, pfn, findings);
+            block_map_pages.push(pfn);
+        }
+        let block_map = read_pages(
+            &self.bytes,
+            &block_map_pages,
+            page_size,
+            num_dir_pages as usize * 4,
+        )
+        .ok_or_else(|| Finding::BadDirectory("Block map is out of bounds!".to_string()))?;
+        let mut offset = 0;
+        let mut dir_pages = Vec::new();
+        for _ in 0..num_dir_pages {
+            let pfn = block_map.gread::<u32>(&mut offset).map_err(bad_directory)?;
+            owners.claim(PageOwner::Directory, pfn, findings);
+            dir_pages.push(pfn);
+        }
+        let dir = read_pages(&self.bytes, &dir_pages, page_size, dir_size as usize)
+            .ok_or_else(|| Finding::BadDirectory("Directory is out of bounds!".to_string()))?;
+        let mut offset = 0;
+        let num_streams = dir.gread::<u32>(&mut offset).map_err(bad_directory)?;
+        let mut sizes = Vec::new();
+        for _ in 0..num_streams {
+            sizes.push(dir.gread::<u32>(&mut offset).map_err(bad_directory)?);
+        }
+        for (index, size) in sizes.into_iter().enumerate() {
+            if size == INVALID_STREAM_SIZE {
+                continue;
+            }
+            for _ in 0..header.pages_needed_to_store(size) {
+                let pfn = dir.gread::<u32>(&mut offset).map_err(bad_directory)?;
+                owners.claim(PageOwner::Stream(index), pfn, findings);
+            }
+        }
+        if offset != dir.len() {
+            findings.push(Finding::DirectorySizeMismatch {
+                expected: offset as u32,
+                actual: dir_size,
+            });
+        }
+        Ok(())
+    }
+    /// Write the stream directory into a copy of the file, parse it again and
+    /// compare every stream.
+    fn verify_round_trip(&self) -> Result<(), Finding> {
+        let round_trip_failed = |e: Error| Finding::RoundTripFailed(e.to_string());
+        let dir = self.get_stream_directory().map_err(round_trip_failed)?;
+        let mut msf = Self::new(self.bytes.clone());
+        msf.set_stream_directory(dir.clone())
+            .map_err(round_trip_failed)?;
+        let new_dir = msf.get_stream_directory().map_err(round_trip_failed)?;
+        if new_dir.streams.len() != dir.streams.len() {
+            return Err(Finding::RoundTripFailed(format!(
+                "Expected {} streams but found {}!",
+                dir.streams.len(),
+                new_dir.streams.len()
+            )));
+        }
+        for (index, (old, new)) in dir.streams.iter().zip(new_dir.streams.iter()).enumerate() {
+            if old.view.as_slice() != new.view.as_slice() {
+                return Err(Finding::RoundTripMismatch { stream: index });
+            }
+        }
+        Ok(())
+    }
+}
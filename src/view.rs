@@ -1,6 +1,14 @@
 // Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
 
-use crate::{msf::MsfBigHeaderMut, pagelist::PageList};
+use std::io::{Seek, SeekFrom, Write};
+
+use crate::{
+    allocator::PageAllocator,
+    error::Error,
+    msf::page_offset,
+    pagelist::PageList,
+    source::{BytesSource, PageSource},
+};
 
 /// This is a linear view of a bunch of pages.
 #[derive(Debug, Default, Clone)]
@@ -13,29 +21,48 @@ pub struct SourceView {
 
 impl SourceView {
     /// Create a source with its known size.
-    pub fn with_size(buff: &[u8], pages: PageList, size: usize) -> Option<SourceView> {
-        Self::new(buff, pages).map(|mut view| {
-            view.bytes.resize(size, 0);
-            view
-        })
+    pub fn with_size(buff: &[u8], pages: PageList, size: usize) -> Result<SourceView, Error> {
+        Self::from_source(&mut BytesSource(buff), pages, size)
     }
     /// Creates a linear view of the pages, flush will write them back.
-    fn new(buff: &[u8], pages: PageList) -> Option<SourceView> {
-        let len = pages.pfns.len() as usize * pages.page_size as usize;
-        let mut bytes = Vec::with_capacity(len);
-        bytes.resize(len, 0);
-        let mut current_offset = 0;
-        for pfn in &pages.pfns {
-            let page = pfn * pages.page_size;
-            if page > buff.len() as u32 {
-                return None;
-            }
-            let slice_end = page + pages.page_size;
-            bytes[current_offset as usize..current_offset as usize + pages.page_size as usize]
-                .copy_from_slice(&buff[page as usize..slice_end as usize]);
-            current_offset += pages.page_size;
+    #[cfg(test)]
+    fn new(buff: &[u8], pages: PageList) -> Result<SourceView, Error> {
+        let size = pages.len() as usize;
+        Self::with_size(buff, pages, size)
+    }
+    /// Read the pages from the source into a view with a known size. Only the
+    /// pages of this view are read.
+    pub fn from_source<S: PageSource + ?Sized>(
+        source: &mut S,
+        pages: PageList,
+        size: usize,
+    ) -> Result<SourceView, Error> {
+        let page_size = pages.page_size as usize;
+        // Check every page before anything is allocated, hostile files can use
+        // the same page over and over to make a view larger than the file.
+        let num_pages = source.len() / page_size.max(1) as u64;
+        if pages.pfns.len() as u64 > num_pages {
+            return Err(Error::TooManyPages {
+                pages: pages.pfns.len() as u64,
+                num_pages,
+            });
         }
-        Some(SourceView { bytes, pages })
+        if let Some(pfn) = pages.pfns.iter().find(|pfn| **pfn as u64 >= num_pages) {
+            return Err(Error::BadPageNumber {
+                pfn: *pfn,
+                num_pages,
+            });
+        }
+        let mut bytes = vec![0u8; pages.pfns.len() * page_size];
+        for (pfn, page) in pages
+            .pfns
+            .iter()
+            .zip(bytes.chunks_exact_mut(page_size.max(1)))
+        {
+            source.read_at(page_offset(*pfn, pages.page_size), page)?;
+        }
+        bytes.resize(size, 0);
+        Ok(SourceView { bytes, pages })
     }
     /// Get a read-only slice of the mapping.
     #[inline(always)]
@@ -47,30 +74,64 @@ impl SourceView {
     pub fn as_mut_slice(&mut self) -> &mut [u8] {
         &mut self.bytes
     }
+    /// Drop the pages this view no longer needs and mark the remaining ones
+    /// as used in the allocator. Every view must be reserved before any view is
+    /// flushed, otherwise pages that are still in use could be handed out.
+    pub fn reserve(&mut self, allocator: &mut PageAllocator) {
+        let pages_needed = allocator.pages_needed_to_store(self.bytes.len());
+        self.pages.pfns.truncate(pages_needed);
+        for pfn in self.pages.pfns.iter() {
+            allocator.mark_used(*pfn);
+        }
+    }
+    /// Same as "flush" but the pages are written to "out" instead of a buffer.
+    /// Whole pages are written, the tail of the last page is zeroed.
+    pub fn write_to<W: Write + Seek>(
+        &mut self,
+        out: &mut W,
+        allocator: &mut PageAllocator,
+    ) -> Result<(), Error> {
+        self.pages.page_size = allocator.page_size();
+        let page_size = allocator.page_size() as usize;
+        let pages_needed = allocator.pages_needed_to_store(self.bytes.len());
+        while self.pages.pfns.len() > pages_needed {
+            if let Some(pfn) = self.pages.pfns.pop() {
+                allocator.release(pfn);
+            }
+        }
+        while self.pages.pfns.len() < pages_needed {
+            self.pages.push(allocator.allocate_page());
+        }
+        let mut page = vec![0u8; page_size];
+        for (pfn, chunk) in self.pages.pfns.iter().zip(self.bytes.chunks(page_size)) {
+            page[..chunk.len()].copy_from_slice(chunk);
+            page[chunk.len()..].fill(0);
+            out.seek(SeekFrom::Start(page_offset(*pfn, allocator.page_size())))?;
+            out.write_all(&page)?;
+        }
+        Ok(())
+    }
     /// This function will flush the internal mapping back
-    /// to the correct pages in "buff". It will also update the page count
-    /// inside of the MSF header so that other flushes which add more pages
-    /// will work correctly.
-    pub fn flush(&mut self, buff: &mut Vec<u8>, header: &mut MsfBigHeaderMut<'_>) {
-        // If we need more pages we need to allocate them now.
-        if self.bytes.len() > self.pages.len() as usize {
-            let cnt_new_pages =
-                header.pages_needed_to_store(self.bytes.len() as u32 - self.pages.len());
-            let high_pfn = header.get_num_pages();
-            for pfn in high_pfn..high_pfn + cnt_new_pages {
-                self.pages.push(pfn);
+    /// to the correct pages in "buff". Pages are taken from the allocator
+    /// if the view grew, and given back to it if the view shrunk.
+    pub fn flush(&mut self, buff: &mut Vec<u8>, allocator: &mut PageAllocator) {
+        // Views of nil streams have no page size yet.
+        self.pages.page_size = allocator.page_size();
+        let pages_needed = allocator.pages_needed_to_store(self.bytes.len());
+        // Release the pages we no longer need.
+        while self.pages.pfns.len() > pages_needed {
+            if let Some(pfn) = self.pages.pfns.pop() {
+                allocator.release(pfn);
             }
-            // Update page count now.
-            header.set_num_pages(high_pfn + cnt_new_pages);
-            buff.resize(
-                buff.len() + (cnt_new_pages * header.get_page_size()) as usize,
-                0,
-            );
+        }
+        // If we need more pages we need to allocate them now.
+        while self.pages.pfns.len() < pages_needed {
+            self.pages.push(allocator.allocate(buff));
         }
         // Now we need to write bytes back to the file at the correct pages.
         let mut current_offset = 0;
         for pfn in self.pages.pfns.iter() {
-            let page_start = *pfn as usize * self.pages.page_size as usize;
+            let page_start = page_offset(*pfn, self.pages.page_size) as usize;
             let bytes_to_copy = std::cmp::min(
                 self.bytes.len() - current_offset,
                 self.pages.page_size as usize,
@@ -85,13 +146,12 @@ impl SourceView {
 #[cfg(test)]
 mod tests {
     use super::SourceView;
-    use crate::{msf::MsfBigHeaderMut, pagelist::PageList};
+    use crate::{allocator::PageAllocator, pagelist::PageList};
 
     // Basic check to know we map 2 pages.
     #[test]
     fn test_source_view1() {
-        let mut buff = Vec::<u8>::new();
-        buff.resize(0x5000, 0);
+        let buff = vec![0u8; 0x5000];
         let mut pages = PageList::new(0x1000);
         pages.push(2);
         pages.push(4);
@@ -103,20 +163,16 @@ mod tests {
     /// Make sure if we make changes they flush back correctly.
     #[test]
     fn flush_source_view1() {
-        let mut buff = Vec::<u8>::new();
-        buff.resize(0x5000, 0);
+        let mut buff = vec![0u8; 0x5000];
         let mut pages = PageList::new(0x1000);
         pages.push(2);
         pages.push(4);
         let mut source = SourceView::new(&buff, pages).unwrap();
         source.as_mut_slice()[0..0x1000].fill(0x69);
         source.as_mut_slice()[0x1000..0x2000].fill(0x42);
-        let mut header_bytes = Vec::<u8>::new();
-        header_bytes.resize(0x1000, 0);
-        let mut header = MsfBigHeaderMut::new(&mut header_bytes).unwrap();
-        header.set_page_size(0x1000);
-        header.set_num_pages(5);
-        source.flush(&mut buff, &mut header);
+        let mut allocator = PageAllocator::new(5, 0x1000);
+        source.reserve(&mut allocator);
+        source.flush(&mut buff, &mut allocator);
         // Make sure the flush actually works.
         assert!(buff[0x2000..0x3000].iter().all(|&e| e == 0x69));
         assert!(buff[0x4000..0x5000].iter().all(|&e| e == 0x42));
@@ -126,8 +182,7 @@ mod tests {
     /// back and expands the vector we flush to.
     #[test]
     fn flush_source_view2() {
-        let mut buff = Vec::<u8>::new();
-        buff.resize(0x5000, 0);
+        let mut buff = vec![0u8; 0x5000];
         let mut pages = PageList::new(0x1000);
         pages.push(2);
         pages.push(4);
@@ -136,17 +191,42 @@ mod tests {
         source.as_mut_slice()[0x1000..0x2000].fill(0x42);
         source.bytes.resize(source.bytes.len() + 0x500, 0xFF);
 
-        let mut header_bytes = Vec::<u8>::new();
-        header_bytes.resize(0x1000, 0);
-        let mut header = MsfBigHeaderMut::new(&mut header_bytes).unwrap();
+        // Page 3 is used by someone else so the file has to grow.
+        let mut allocator = PageAllocator::new(5, 0x1000);
+        allocator.mark_used(3);
+        source.reserve(&mut allocator);
+        source.flush(&mut buff, &mut allocator);
 
-        header.set_page_size(0x1000);
-        header.set_num_pages(5);
-        source.flush(&mut buff, &mut header);
-
-        assert_eq!(header.get_num_pages(), 6);
+        assert_eq!(allocator.num_pages(), 6);
+        assert_eq!(buff.len(), 0x6000);
         // Make sure the flush actually works.
         assert!(buff[0x2000..0x3000].iter().all(|&e| e == 0x69));
         assert!(buff[0x4000..0x5000].iter().all(|&e| e == 0x42));
+        assert!(buff[0x5000..0x5500].iter().all(|&e| e == 0xFF));
+    }
+
+    /// Shrink one mapping and grow another, the released page
+    /// must be reused instead of growing the file.
+    #[test]
+    fn flush_source_view3() {
+        let mut buff = vec![0u8; 0x5000];
+        let mut pages = PageList::new(0x1000);
+        pages.push(3);
+        pages.push(4);
+        let mut source1 = SourceView::new(&buff, pages).unwrap();
+        source1.bytes.truncate(0x800);
+        let mut source2 = SourceView::new(&buff, PageList::new(0x1000)).unwrap();
+        source2.bytes.resize(0x1000, 0x42);
+
+        let mut allocator = PageAllocator::new(5, 0x1000);
+        source1.reserve(&mut allocator);
+        source2.reserve(&mut allocator);
+        source1.flush(&mut buff, &mut allocator);
+        source2.flush(&mut buff, &mut allocator);
+
+        assert_eq!(source1.pages.pfns, vec![3]);
+        assert_eq!(source2.pages.pfns, vec![4]);
+        assert_eq!(allocator.num_pages(), 5);
+        assert!(buff[0x4000..0x5000].iter().all(|&e| e == 0x42));
     }
 }
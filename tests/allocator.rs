@@ -0,0 +1,89 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use elderscroll::{directory::Stream, msf::BigMsf, pagelist::PageList, view::SourceView};
+
+/// Rewrite the same stream over and over, the file must not keep growing.
+#[test]
+fn allocator_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let page_size = msf.header().unwrap().get_page_size();
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let stream_index = stream_directory.streams.len();
+    stream_directory.streams.push(Stream {
+        original_stream_size: Default::default(),
+        view: SourceView {
+            bytes: vec![0x69; page_size as usize * 2],
+            pages: PageList::new(page_size),
+        },
+    });
+    msf.set_stream_directory(stream_directory).unwrap();
+    let file_size = msf.bytes.len();
+    for i in 0..4 {
+        // Replace the stream with a brand new one, the old pages must be reused.
+        let mut stream_directory = msf.get_stream_directory().unwrap();
+        stream_directory.streams[stream_index] = Stream {
+            original_stream_size: Default::default(),
+            view: SourceView {
+                bytes: vec![i; page_size as usize * 2],
+                pages: PageList::new(page_size),
+            },
+        };
+        msf.set_stream_directory(stream_directory).unwrap();
+        assert_eq!(msf.bytes.len(), file_size);
+        let stream_directory = msf.get_stream_directory().unwrap();
+        let stream = &stream_directory.streams[stream_index];
+        assert!(stream.view.as_slice().iter().all(|&e| e == i));
+    }
+}
+
+/// Shrink a stream, its released pages are reused and zeroed.
+#[test]
+fn allocator_test2() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut msf = BigMsf::new(bytes.to_vec());
+    msf.zero_free_pages = true;
+    let header = msf.header().unwrap();
+    let page_size = header.get_page_size() as usize;
+    let num_pages = header.get_num_pages();
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    // Find the biggest stream and cut it down to a single page.
+    let (stream_index, released) = stream_directory
+        .streams
+        .iter()
+        .enumerate()
+        .map(|(i, stream)| (i, stream.view.pages.pfns.clone()))
+        .max_by_key(|(_, pfns)| pfns.len())
+        .unwrap();
+    stream_directory.streams[stream_index]
+        .view
+        .bytes
+        .truncate(page_size);
+    msf.set_stream_directory(stream_directory).unwrap();
+    assert_eq!(msf.header().unwrap().get_num_pages(), num_pages);
+    let stream_directory = msf.get_stream_directory().unwrap();
+    assert_eq!(
+        stream_directory.streams[stream_index].view.pages.pfns,
+        released[..1]
+    );
+    // Every released page which nobody reused must be zero.
+    let used = stream_directory
+        .streams
+        .iter()
+        .flat_map(|stream| stream.view.pages.pfns.iter())
+        .chain(stream_directory.view.pages.pfns.iter())
+        .copied()
+        .collect::<Vec<_>>();
+    for pfn in released[1..].iter().filter(|pfn| !used.contains(pfn)) {
+        let page_start = *pfn as usize * page_size;
+        assert!(msf.bytes[page_start..page_start + page_size]
+            .iter()
+            .all(|&e| e == 0));
+    }
+}
@@ -0,0 +1,49 @@
+// Source of the HelloWorld_lld_* fixtures, which lld-link wrote without any
+// C runtime:
+//
+// rustc +nightly --target x86_64-pc-windows-msvc --crate-type bin -C debuginfo=2 \
+//     -C opt-level=1 -C panic=abort --emit=obj -o HelloWorld_lld_x64.obj HelloWorld_lld.rs
+// rustc +nightly --target i686-pc-windows-msvc --crate-type bin -C debuginfo=2 \
+//     -C opt-level=1 -C panic=abort --emit=obj -o HelloWorld_lld_x86.obj HelloWorld_lld.rs
+// rust-lld -flavor link /debug /nodefaultlib /entry:mainCRTStartup /subsystem:console \
+//     /machine:x64 /out:HelloWorld_lld_x64.exe HelloWorld_lld_x64.obj
+// rust-lld -flavor link /debug /nodefaultlib /entry:mainCRTStartup /subsystem:console \
+//     /machine:x86 /safeseh:no /out:HelloWorld_lld_x86.exe HelloWorld_lld_x86.obj
+// rust-lld -flavor link /debug /nodefaultlib /entry:mainCRTStartup /subsystem:console \
+//     /machine:x64 /pdbpagesize:8192 /out:HelloWorld_lld_x64_8k.exe HelloWorld_lld_x64.obj
+
+#![feature(no_core, lang_items, auto_traits)]
+#![no_core]
+#![no_std]
+#![no_main]
+#![allow(internal_features)]
+
+#[lang = "pointee_sized"]
+pub trait PointeeSized {}
+#[lang = "meta_sized"]
+pub trait MetaSized: PointeeSized {}
+#[lang = "sized"]
+pub trait Sized: MetaSized {}
+#[lang = "copy"]
+pub trait Copy {}
+impl Copy for u32 {}
+
+pub struct Point {
+    pub x: u32,
+    pub y: u32,
+}
+
+#[inline(never)]
+#[no_mangle]
+pub extern "C" fn add(point: &Point) -> u32 {
+    point.x
+}
+
+#[no_mangle]
+pub extern "C" fn mainCRTStartup() -> u32 {
+    let point = Point { x: 0x69, y: 0x42 };
+    add(&point)
+}
+
+#[lang = "freeze"]
+pub unsafe auto trait Freeze {}
@@ -0,0 +1,109 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::io::Cursor;
+
+use elderscroll::{
+    builder::{PdbBuilder, IMAGE_FILE_MACHINE_AMD64, NAMES_STREAM_INDEX},
+    dbi::DbiStream,
+    directory::{Stream, DBI_STREAM_INDEX, INVALID_STREAM_INDEX},
+    msf::BigMsf,
+    omap::{OmapEntry, OmapStream},
+    pagelist::PageList,
+    view::SourceView,
+};
+
+const GUID: [u8; 16] = [
+    0x69, 0x42, 0x13, 0x37, 0xDE, 0xAD, 0xBE, 0xEF, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08,
+];
+
+/// Build an empty PDB and open it with the pdb crate.
+#[test]
+fn builder_test1() {
+    for page_size in [0x200, 0x1000, 0x8000] {
+        let msf = PdbBuilder::new()
+            .page_size(page_size)
+            .signature(0x65EFED7B)
+            .age(3)
+            .guid(GUID)
+            .build()
+            .unwrap();
+        let header = msf.header().unwrap();
+        assert_eq!(header.get_page_size(), page_size);
+        assert_eq!(header.file_size(), msf.bytes.len() as u64);
+        let stream_directory = msf.get_stream_directory().unwrap();
+        assert_eq!(stream_directory.streams.len(), NAMES_STREAM_INDEX + 1);
+        // Every optional debug stream is missing.
+        let dbi = DbiStream::new(stream_directory.streams[DBI_STREAM_INDEX].clone());
+        assert_eq!(dbi.header().unwrap().get_age(), 3);
+        assert_eq!(
+            dbi.header().unwrap().get_machine(),
+            IMAGE_FILE_MACHINE_AMD64
+        );
+        let extras = dbi.extra_streams().unwrap();
+        assert_eq!(extras.get_section_headers(), INVALID_STREAM_INDEX);
+        assert_eq!(extras.get_omap_to_src(), INVALID_STREAM_INDEX);
+
+        let mut pdb = pdb::PDB::open(Cursor::new(msf.bytes)).unwrap();
+        let info = pdb.pdb_information().unwrap();
+        assert_eq!(info.signature, 0x65EFED7B);
+        assert_eq!(info.age, 3);
+        // The GUID is stored as little endian Data1, Data2 and Data3 fields.
+        let (data1, data2, data3, data4) = info.guid.as_fields();
+        assert_eq!(data1, 0x37134269);
+        assert_eq!(data2, 0xADDE);
+        assert_eq!(data3, 0xEFBE);
+        assert_eq!(data4, &GUID[8..]);
+        assert_eq!(info.guid.to_bytes_le(), GUID);
+        assert_eq!(pdb.debug_information().unwrap().age(), Some(3));
+        assert_eq!(pdb.type_information().unwrap().len(), 0);
+        assert_eq!(pdb.id_information().unwrap().len(), 0);
+        pdb.string_table().unwrap();
+    }
+}
+
+/// Add OMAP streams to a synthetic PDB, like omap_test1 does for HelloWorld.pdb.
+#[test]
+fn builder_test2() {
+    let msf = PdbBuilder::new().build().unwrap();
+    let page_size = msf.header().unwrap().get_page_size();
+    let mut msf = BigMsf::new(msf.bytes);
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let mut dbi = DbiStream::new(stream_directory.streams[DBI_STREAM_INDEX].clone());
+    let omap_stream_index = stream_directory.streams.len();
+    let mut extras = dbi.extra_streams_mut().unwrap();
+    extras.set_omap_to_src(omap_stream_index as u16);
+    extras.set_omap_from_src(omap_stream_index as u16 + 1);
+    extras.set_section_headers(omap_stream_index as u16 + 2);
+    extras.set_original_section_headers(omap_stream_index as u16 + 2);
+    let mut omap_to_src = OmapStream::default();
+    omap_to_src.0.insert(OmapEntry(0x1000, 0x2000));
+    omap_to_src.0.insert(OmapEntry(0x1010, 0x2010));
+    let mut omap_from_src = OmapStream::default();
+    omap_from_src.0.insert(OmapEntry(0x2000, 0x1000));
+    omap_from_src.0.insert(OmapEntry(0x2010, 0x1010));
+    // A single ".text" section at 0x1000 with a size of 0x2000.
+    let mut section_headers = vec![0u8; 40];
+    section_headers[..5].copy_from_slice(b".text");
+    section_headers[8..12].copy_from_slice(&0x2000u32.to_le_bytes());
+    section_headers[12..16].copy_from_slice(&0x1000u32.to_le_bytes());
+    for bytes in [
+        omap_to_src.to_vec().unwrap(),
+        omap_from_src.to_vec().unwrap(),
+        section_headers,
+    ] {
+        stream_directory.streams.push(Stream {
+            original_stream_size: Default::default(),
+            view: SourceView {
+                bytes,
+                pages: PageList::new(page_size),
+            },
+        });
+    }
+    stream_directory.streams[DBI_STREAM_INDEX] = dbi.stream;
+    msf.set_stream_directory(stream_directory).unwrap();
+
+    let mut pdb = pdb::PDB::open(Cursor::new(msf.bytes)).unwrap();
+    let address_map = pdb.address_map().unwrap();
+    let rva = pdb::Rva(0x1008).to_internal_rva(&address_map);
+    assert_eq!(rva, Some(pdb::PdbInternalRva(0x2008)));
+}
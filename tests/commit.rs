@@ -0,0 +1,140 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::io::{Cursor, Seek, SeekFrom, Write};
+
+use elderscroll::{
+    directory::DBI_STREAM_INDEX,
+    msf::{BigMsf, SyncWrite},
+};
+
+/// Writer which remembers the offsets it wrote to and when it synced (None).
+/// With "crash" set it fails as soon as the header page is written, like a
+/// crash right before the last write of the commit.
+struct JournalWriter {
+    file: Cursor<Vec<u8>>,
+    crash: bool,
+    journal: Vec<Option<u64>>,
+}
+
+impl Write for JournalWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.crash && self.file.position() == 0 {
+            return Err(std::io::Error::other("Crash!"));
+        }
+        self.journal.push(Some(self.file.position()));
+        self.file.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for JournalWriter {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl SyncWrite for JournalWriter {
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.journal.push(None);
+        Ok(())
+    }
+}
+
+/// Changed streams move to new pages and the FPM flips between both pages.
+#[test]
+fn commit_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let old_dir = msf.get_stream_directory().unwrap();
+    let mut dir = old_dir.clone();
+    dir.streams[DBI_STREAM_INDEX].view.bytes[0x40] ^= 0xFF;
+    msf.commit_stream_directory(dir.clone()).unwrap();
+    assert_eq!(msf.header().unwrap().get_free_page_map(), 2);
+    assert_eq!(msf.verify(), vec![]);
+    let new_dir = msf.get_stream_directory().unwrap();
+    assert_ne!(
+        new_dir.streams[DBI_STREAM_INDEX].view.pages.pfns,
+        old_dir.streams[DBI_STREAM_INDEX].view.pages.pfns
+    );
+    for (index, (old, new)) in old_dir
+        .streams
+        .iter()
+        .zip(new_dir.streams.iter())
+        .enumerate()
+    {
+        assert_eq!(dir.streams[index].view.bytes, new.view.bytes);
+        if index != DBI_STREAM_INDEX {
+            assert_eq!(old.view.pages.pfns, new.view.pages.pfns);
+        }
+    }
+    // The old header page still describes the old state of the file.
+    let mut old_state = msf.bytes.clone();
+    old_state[..0x1000].copy_from_slice(&bytes[..0x1000]);
+    let old_state = BigMsf::new(old_state);
+    for (old, new) in old_dir
+        .streams
+        .iter()
+        .zip(old_state.get_stream_directory().unwrap().streams.iter())
+    {
+        assert_eq!(old.view.bytes, new.view.bytes);
+    }
+    // A second commit flips back to the first FPM page.
+    let mut dir = msf.get_stream_directory().unwrap();
+    dir.streams[DBI_STREAM_INDEX].view.bytes[0x40] ^= 0xFF;
+    msf.commit_stream_directory(dir).unwrap();
+    assert_eq!(msf.header().unwrap().get_free_page_map(), 1);
+    assert_eq!(msf.verify(), vec![]);
+}
+
+/// The header page is written last, a crash before it leaves the old file.
+#[test]
+fn commit_test2() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut dir = BigMsf::new(bytes.to_vec()).get_stream_directory().unwrap();
+    dir.streams[DBI_STREAM_INDEX]
+        .view
+        .bytes
+        .resize(0x4000, 0x69);
+
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let mut file = JournalWriter {
+        file: Cursor::new(bytes.to_vec()),
+        crash: false,
+        journal: Vec::new(),
+    };
+    msf.commit_to(dir.clone(), &mut file).unwrap();
+    assert_eq!(file.file.get_ref(), &msf.bytes);
+    // The pages are synced before the header page is written, which is synced
+    // as well.
+    let header = file.journal.iter().position(|entry| *entry == Some(0));
+    let header = header.unwrap();
+    assert!(header > 1);
+    assert_eq!(file.journal[header - 1], None);
+    assert_eq!(file.journal[header + 1..], [None]);
+
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let mut file = JournalWriter {
+        file: Cursor::new(bytes.to_vec()),
+        crash: true,
+        journal: Vec::new(),
+    };
+    assert!(msf.commit_to(dir, &mut file).is_err());
+    let crashed = BigMsf::new(file.file.into_inner());
+    assert_eq!(crashed.bytes[..0x1000], bytes[..0x1000]);
+    let old_dir = BigMsf::new(bytes.to_vec()).get_stream_directory().unwrap();
+    for (old, new) in old_dir
+        .streams
+        .iter()
+        .zip(crashed.get_stream_directory().unwrap().streams.iter())
+    {
+        assert_eq!(old.view.bytes, new.view.bytes);
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use elderscroll::{directory::Stream, msf::BigMsf, pagelist::PageList, view::SourceView};
+
+/// Scatter the streams of HelloWorld.pdb around and compact it again.
+#[test]
+fn compact_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let page_size = msf.header().unwrap().get_page_size();
+    // Grow every other stream so they get scattered to the end of the file,
+    // then shrink them again which leaves dead pages behind.
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    for stream in stream_directory.streams.iter_mut().step_by(2) {
+        let len = stream.view.bytes.len();
+        stream.view.bytes.resize(len + page_size as usize, 0x69);
+    }
+    stream_directory.streams.push(Stream {
+        original_stream_size: Default::default(),
+        view: SourceView {
+            bytes: vec![0x42; page_size as usize * 8],
+            pages: PageList::new(page_size),
+        },
+    });
+    msf.set_stream_directory(stream_directory).unwrap();
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    for stream in stream_directory.streams.iter_mut().step_by(2) {
+        let len = stream.view.bytes.len();
+        stream.view.bytes.truncate(len - page_size as usize);
+    }
+    stream_directory
+        .streams
+        .last_mut()
+        .unwrap()
+        .view
+        .bytes
+        .clear();
+    msf.set_stream_directory(stream_directory).unwrap();
+    let before = msf.get_stream_directory().unwrap();
+    let num_pages = msf.header().unwrap().get_num_pages();
+
+    msf.compact().unwrap();
+    let header = msf.header().unwrap();
+    assert!(header.get_num_pages() < num_pages);
+    assert_eq!(header.get_num_pages() * page_size, msf.bytes.len() as u32);
+    assert_eq!(msf.verify(), vec![]);
+    let after = msf.get_stream_directory().unwrap();
+    assert_eq!(before.streams.len(), after.streams.len());
+    // Header, FPM pages, then every stream in order, the directory and the block map.
+    let mut expected_pfn = 3;
+    for (before, after) in before.streams.iter().zip(after.streams.iter()) {
+        assert_eq!(before.view.bytes, after.view.bytes);
+        for pfn in after.view.pages.pfns.iter() {
+            assert_eq!(*pfn, expected_pfn);
+            expected_pfn += 1;
+        }
+    }
+    for pfn in after.view.pages.pfns.iter() {
+        assert_eq!(*pfn, expected_pfn);
+        expected_pfn += 1;
+    }
+    assert_eq!(after.block_map.pages.pfns, vec![expected_pfn]);
+    assert_eq!(header.get_num_pages(), expected_pfn + 1);
+}
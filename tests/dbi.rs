@@ -0,0 +1,202 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::io::Cursor;
+
+use elderscroll::{
+    builder::PdbBuilder,
+    dbi::{DbiStream, DebugStreamKind},
+    error::Error,
+    msf::BigMsf,
+    omap::{OmapEntry, OmapStream},
+};
+use pdb::FallibleIterator;
+
+/// Read, write and remove optional debug streams of HelloWorld.pdb by kind.
+#[test]
+fn dbi_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let mut dbi = DbiStream::from_stream_directory(&stream_directory).unwrap();
+    for kind in DebugStreamKind::ALL {
+        let index = dbi.debug_stream_index(kind).unwrap();
+        let expected = (kind == DebugStreamKind::SectionHeaders).then_some(12);
+        assert_eq!(index, expected);
+    }
+    let section_headers = dbi
+        .debug_stream(&stream_directory, DebugStreamKind::SectionHeaders)
+        .unwrap()
+        .unwrap()
+        .to_vec();
+    assert_eq!(section_headers.len() % 40, 0);
+    assert_eq!(
+        dbi.debug_stream(&stream_directory, DebugStreamKind::Pdata)
+            .unwrap(),
+        None
+    );
+
+    // Missing streams are added, existing ones are reused.
+    let num_streams = stream_directory.streams.len();
+    let pdata = dbi
+        .write_debug_stream(&mut stream_directory, DebugStreamKind::Pdata, vec![1; 12])
+        .unwrap();
+    assert_eq!(pdata, num_streams);
+    let index = dbi
+        .write_debug_stream(&mut stream_directory, DebugStreamKind::Pdata, vec![2; 24])
+        .unwrap();
+    assert_eq!(index, pdata);
+    // Aliased streams are not overwritten.
+    dbi.set_debug_stream_index(DebugStreamKind::OriginalSectionHeaders, Some(12))
+        .unwrap();
+    let index = dbi
+        .write_debug_stream(
+            &mut stream_directory,
+            DebugStreamKind::OriginalSectionHeaders,
+            vec![3; 40],
+        )
+        .unwrap();
+    assert_eq!(index, num_streams + 1);
+    assert_eq!(stream_directory.streams[12].view.bytes, section_headers);
+    // Streams that point at fixed streams are not overwritten either.
+    dbi.set_debug_stream_index(DebugStreamKind::Xdata, Some(1))
+        .unwrap();
+    dbi.remove_debug_stream(&mut stream_directory, DebugStreamKind::Xdata)
+        .unwrap();
+    assert!(!stream_directory.streams[1].is_nil());
+    assert!(matches!(
+        dbi.set_debug_stream_index(DebugStreamKind::Fpo, Some(0xFFFF)),
+        Err(Error::StreamIndexTooLarge(0xFFFF))
+    ));
+    dbi.flush(&mut stream_directory).unwrap();
+    msf.set_stream_directory(stream_directory).unwrap();
+
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let mut dbi = DbiStream::from_stream_directory(&stream_directory).unwrap();
+    let bytes = dbi
+        .debug_stream(&stream_directory, DebugStreamKind::Pdata)
+        .unwrap();
+    assert_eq!(bytes, Some(&[2u8; 24][..]));
+    assert_eq!(
+        dbi.debug_stream_index(DebugStreamKind::Xdata).unwrap(),
+        None
+    );
+    dbi.remove_debug_stream(&mut stream_directory, DebugStreamKind::Pdata)
+        .unwrap();
+    assert!(stream_directory.streams[pdata].is_nil());
+    assert_eq!(
+        dbi.debug_stream_index(DebugStreamKind::Pdata).unwrap(),
+        None
+    );
+}
+
+/// "set_omap" adds OMAP streams once and reuses them afterwards, the saved PDB
+/// maps addresses through them.
+#[test]
+fn dbi_test2() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let mut dbi = DbiStream::from_stream_directory(&stream_directory).unwrap();
+    assert_eq!(dbi.omap(&stream_directory).unwrap(), (None, None));
+    let mut to_src = OmapStream::default();
+    to_src.0.insert(OmapEntry(0x1000, 0x1000));
+    let mut from_src = OmapStream::default();
+    from_src.0.insert(OmapEntry(0x1000, 0x1000));
+    dbi.set_omap(&mut stream_directory, &to_src, &from_src)
+        .unwrap();
+    let num_streams = stream_directory.streams.len();
+    to_src.0.insert(OmapEntry(0x2000, 0x1800));
+    from_src.0.insert(OmapEntry(0x1800, 0x2000));
+    dbi.set_omap(&mut stream_directory, &to_src, &from_src)
+        .unwrap();
+    assert_eq!(stream_directory.streams.len(), num_streams);
+    assert_eq!(
+        dbi.debug_stream_index(DebugStreamKind::OriginalSectionHeaders)
+            .unwrap(),
+        Some(12)
+    );
+    dbi.flush(&mut stream_directory).unwrap();
+    msf.set_stream_directory(stream_directory).unwrap();
+
+    let stream_directory = msf.get_stream_directory().unwrap();
+    let dbi = DbiStream::from_stream_directory(&stream_directory).unwrap();
+    assert_eq!(
+        dbi.omap(&stream_directory).unwrap(),
+        (Some(to_src), Some(from_src))
+    );
+    let mut pdb = pdb::PDB::open(Cursor::new(msf.bytes)).unwrap();
+    pdb.address_map().unwrap();
+
+    // PdbBuilder writes no optional debug streams at all.
+    let msf = PdbBuilder::new().build().unwrap();
+    let stream_directory = msf.get_stream_directory().unwrap();
+    let dbi = DbiStream::from_stream_directory(&stream_directory).unwrap();
+    for kind in DebugStreamKind::ALL {
+        assert_eq!(dbi.debug_stream(&stream_directory, kind).unwrap(), None);
+    }
+}
+
+/// Split HelloWorld.pdb's DBI stream into substreams, grow and shrink them
+/// and write them back with padded, recomputed sizes.
+#[test]
+fn dbi_test3() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let mut dbi = DbiStream::from_stream_directory(&stream_directory).unwrap();
+    let original = dbi.stream.view.bytes.clone();
+    let mut substreams = dbi.substreams().unwrap();
+    assert_eq!(substreams.section_map.len(), 144);
+    assert_eq!(substreams.ec.len(), 345);
+    assert_eq!(substreams.optional_dbg_header.len(), 24);
+    dbi.set_substreams(&substreams).unwrap();
+    assert_eq!(dbi.stream.view.bytes, original);
+
+    // 3 bytes of type server map are padded to 4, the EC substream is not.
+    substreams.type_server_map = vec![0x69; 3];
+    substreams.ec.push(0);
+    substreams.section_contributions.truncate(4 + 28 * 10);
+    dbi.set_substreams(&substreams).unwrap();
+    let header = dbi.header().unwrap();
+    assert_eq!(header.get_type_server_map_size(), 4);
+    assert_eq!(header.get_ec_substream_size(), 346);
+    assert_eq!(header.get_section_contribution_size(), 4 + 28 * 10);
+    let saved = dbi.substreams().unwrap();
+    assert_eq!(saved.type_server_map, [0x69, 0x69, 0x69, 0]);
+    assert_eq!(saved.ec, substreams.ec);
+    assert_eq!(saved.module_info, substreams.module_info);
+    assert_eq!(
+        dbi.debug_stream_index(DebugStreamKind::SectionHeaders)
+            .unwrap(),
+        Some(12)
+    );
+    dbi.flush(&mut stream_directory).unwrap();
+    msf.set_stream_directory(stream_directory).unwrap();
+    let mut pdb = pdb::PDB::open(Cursor::new(msf.bytes)).unwrap();
+    let dbi = pdb.debug_information().unwrap();
+    assert_eq!(dbi.modules().unwrap().count().unwrap(), 49);
+    assert_eq!(dbi.section_contributions().unwrap().count().unwrap(), 10);
+
+    // Substreams past the end of the DBI stream are errors.
+    let mut dbi = DbiStream::new(
+        BigMsf::new(bytes.to_vec())
+            .get_stream_directory()
+            .unwrap()
+            .streams[3]
+            .clone(),
+    );
+    dbi.stream.view.bytes.truncate(0x40 + 11864 + 8432);
+    assert!(matches!(
+        dbi.substreams(),
+        Err(Error::SubstreamOutOfBounds { .. })
+    ));
+}
@@ -0,0 +1,121 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use elderscroll::{
+    directory::Stream,
+    msf::{BigMsf, MsfBigHeaderMut, MAGIC},
+    pagelist::PageList,
+    view::SourceView,
+};
+
+/// Build an MSF with no streams. Page 3 is the block map and page 4 the directory.
+fn empty_msf(page_size: u32) -> BigMsf {
+    let mut bytes = vec![0u8; page_size as usize * 5];
+    let mut header = MsfBigHeaderMut::new(&mut bytes).unwrap();
+    header.set_magic(MAGIC.try_into().unwrap());
+    header.set_page_size(page_size);
+    header.set_free_page_map(1);
+    header.set_num_pages(5);
+    header.set_stream_dir_size(4);
+    header.set_stream_block_map(3);
+    bytes[page_size as usize * 3] = 4;
+    BigMsf::new(bytes)
+}
+
+fn push_streams(msf: &mut BigMsf, count: usize) {
+    let page_size = msf.header().unwrap().get_page_size();
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    for i in 0..count {
+        stream_directory.streams.push(Stream {
+            original_stream_size: Default::default(),
+            view: SourceView {
+                bytes: (i as u32).to_le_bytes().to_vec(),
+                pages: PageList::new(page_size),
+            },
+        });
+    }
+    msf.set_stream_directory(stream_directory).unwrap();
+}
+
+/// A directory which needs more than one block map page.
+#[test]
+fn directory_test1() {
+    let mut msf = empty_msf(512);
+    push_streams(&mut msf, 20000);
+    let header = msf.header().unwrap();
+    // 4 + 20000 * 8 bytes of directory is 313 pages, 128 PFN's per block map page.
+    assert_eq!(header.get_stream_dir_size(), 4 + 20000 * 8);
+    let stream_directory = msf.get_stream_directory().unwrap();
+    assert_eq!(stream_directory.block_map.pages.pfns.len(), 3);
+    assert_eq!(
+        stream_directory.block_map.pages.pfns[0],
+        header.get_stream_block_map()
+    );
+    assert_eq!(stream_directory.streams.len(), 20000);
+    for (i, stream) in stream_directory.streams.iter().enumerate() {
+        assert_eq!(stream.view.as_slice(), (i as u32).to_le_bytes());
+    }
+    // Shrink the directory again, the extra block map pages go away.
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    stream_directory.streams.truncate(10);
+    msf.set_stream_directory(stream_directory).unwrap();
+    let stream_directory = msf.get_stream_directory().unwrap();
+    assert_eq!(stream_directory.block_map.pages.pfns.len(), 1);
+    assert_eq!(&msf.bytes[0x38..0x3C], [0u8; 4]);
+    assert_eq!(stream_directory.streams.len(), 10);
+    assert_eq!(msf.verify(), vec![]);
+}
+
+/// A directory which does not fit in the block map array must be refused
+/// without touching the file.
+#[test]
+fn directory_test2() {
+    let mut msf = empty_msf(512);
+    push_streams(&mut msf, 1);
+    let bytes = msf.bytes.clone();
+    // 115 block map pages of 128 PFN's each can describe 7536640 bytes of directory.
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    stream_directory
+        .streams
+        .resize(7536640 / 4, Stream::default());
+    assert!(msf.set_stream_directory(stream_directory).is_err());
+    assert_eq!(msf.bytes, bytes);
+}
+
+/// Add, replace and remove streams without touching the other stream indices.
+#[test]
+fn directory_test3() {
+    let mut msf = empty_msf(0x1000);
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    assert_eq!(stream_directory.add_stream(vec![0x69; 0x2000]), 0);
+    assert_eq!(stream_directory.add_stream(vec![0x42; 0x10]), 1);
+    assert_eq!(stream_directory.add_stream(vec![0x13; 0x1000]), 2);
+    msf.set_stream_directory(stream_directory).unwrap();
+
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let freed = stream_directory.stream(0).unwrap().view.pages.pfns.clone();
+    stream_directory.remove_stream(0).unwrap();
+    stream_directory
+        .replace_stream(1, vec![0x37; 0x1800])
+        .unwrap();
+    assert!(stream_directory.stream(0).unwrap().is_nil());
+    assert!(stream_directory.stream(3).is_err());
+    assert!(stream_directory.replace_stream(3, Vec::new()).is_err());
+    assert!(stream_directory.remove_stream(3).is_err());
+    msf.set_stream_directory(stream_directory).unwrap();
+    assert_eq!(msf.verify(), vec![]);
+
+    let stream_directory = msf.get_stream_directory().unwrap();
+    assert_eq!(stream_directory.streams.len(), 3);
+    assert!(stream_directory.stream(0).unwrap().is_nil());
+    assert_eq!(
+        stream_directory.stream(1).unwrap().view.bytes,
+        vec![0x37; 0x1800]
+    );
+    assert_eq!(
+        stream_directory.stream(2).unwrap().view.bytes,
+        vec![0x13; 0x1000]
+    );
+    // The pages of the removed stream were reused by the stream that grew.
+    let pfns = &stream_directory.stream(1).unwrap().view.pages.pfns;
+    assert!(pfns.iter().any(|pfn| freed.contains(pfn)));
+}
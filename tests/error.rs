@@ -0,0 +1,99 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use elderscroll::{
+    builder::PdbBuilder,
+    dbi::DbiStream,
+    directory::DBI_STREAM_INDEX,
+    error::Error,
+    msf::{BigMsf, BLOCK_MAP_OFFSET},
+    msfz::Compression,
+};
+
+/// Corrupted and unsupported input give errors which can be told apart.
+#[test]
+fn error_test1() {
+    let msf = PdbBuilder::new().build().unwrap();
+    let header = msf.header().unwrap();
+    let page_size = header.get_page_size() as usize;
+    let num_pages = header.get_num_pages();
+    let stream_directory = msf.get_stream_directory().unwrap();
+    let dir_page = stream_directory.view.pages.pfns[0] as usize * page_size;
+
+    let e = BigMsf::open(vec![0u8; page_size]).unwrap_err();
+    assert!(matches!(e, Error::BadMagic));
+    assert!(e.is_unsupported());
+    assert!(Compression::from_u32(7).unwrap_err().is_unsupported());
+    assert!(matches!(
+        BigMsf::new(vec![]).header(),
+        Err(Error::TruncatedHeader(_))
+    ));
+
+    // The page size is not one MSF files can use.
+    let mut bytes = msf.bytes.clone();
+    bytes[0x20..0x24].copy_from_slice(&0x1234u32.to_le_bytes());
+    assert!(matches!(
+        BigMsf::new(bytes).get_stream_directory(),
+        Err(Error::BadPageSize(0x1234))
+    ));
+
+    // The block map is past the end of the file.
+    let mut bytes = msf.bytes.clone();
+    bytes[BLOCK_MAP_OFFSET..BLOCK_MAP_OFFSET + 4].copy_from_slice(&(num_pages + 1).to_le_bytes());
+    assert!(matches!(
+        BigMsf::new(bytes).get_stream_directory(),
+        Err(Error::BadPageNumber { pfn, .. }) if pfn == num_pages + 1
+    ));
+
+    // The page of the DBI stream is past the end of the file.
+    let mut bytes = msf.bytes.clone();
+    // Number of streams, 6 sizes, then the pages of stream 1, 2 and 3.
+    let offset = dir_page + 4 + 6 * 4 + 2 * 4;
+    bytes[offset..offset + 4].copy_from_slice(&(num_pages + 1).to_le_bytes());
+    let e = BigMsf::new(bytes).get_stream_directory().unwrap_err();
+    assert!(matches!(
+        e,
+        Error::TruncatedStream {
+            index: DBI_STREAM_INDEX,
+            offset: 0
+        }
+    ));
+    assert!(!e.is_unsupported());
+
+    // The directory is cut short.
+    let mut bytes = msf.bytes.clone();
+    bytes[0x2C..0x30].copy_from_slice(&12u32.to_le_bytes());
+    assert!(matches!(
+        BigMsf::new(bytes).get_stream_directory(),
+        Err(Error::TruncatedDirectory { offset: 12 })
+    ));
+}
+
+/// Streams and substreams which do not exist.
+#[test]
+#[allow(deprecated)]
+fn error_test2() {
+    let msf = PdbBuilder::new().build().unwrap();
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let num_streams = stream_directory.streams.len();
+    assert!(matches!(
+        stream_directory.stream(num_streams),
+        Err(Error::MissingStream(index)) if index == num_streams
+    ));
+
+    let mut dbi = DbiStream::new(stream_directory.streams[DBI_STREAM_INDEX].clone());
+    dbi.header_mut().unwrap().set_ec_substream_size(0x10000);
+    assert!(matches!(
+        dbi.extra_streams(),
+        Err(Error::SubstreamOutOfBounds {
+            substream: "optional debug header",
+            ..
+        })
+    ));
+    assert!(dbi.nop_section_maps().is_ok());
+    assert!(dbi.section_map().is_ok());
+
+    stream_directory.remove_stream(DBI_STREAM_INDEX).unwrap();
+    let dbi = DbiStream::new(stream_directory.streams[DBI_STREAM_INDEX].clone());
+    assert!(matches!(dbi.header(), Err(Error::MissingDbiStream)));
+    assert!(matches!(dbi.extra_streams(), Err(Error::MissingDbiStream)));
+}
@@ -0,0 +1,49 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use elderscroll::{
+    directory::Stream, fpm::is_reserved_page, msf::BigMsf, pagelist::PageList, view::SourceView,
+};
+
+/// Grow the PDB and make sure the FPM marks exactly the pages in use as used.
+#[test]
+fn fpm_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let page_size = msf.header().unwrap().get_page_size();
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    stream_directory.streams.push(Stream {
+        original_stream_size: Default::default(),
+        view: SourceView {
+            bytes: vec![0x69; page_size as usize * 3 + 1],
+            pages: PageList::new(page_size),
+        },
+    });
+    msf.set_stream_directory(stream_directory).unwrap();
+    let header = msf.header().unwrap();
+    let num_pages = header.get_num_pages();
+    assert_eq!(num_pages * page_size, msf.bytes.len() as u32);
+    // Collect every page referenced by the MSF.
+    let stream_directory = msf.get_stream_directory().unwrap();
+    let mut used = vec![false; num_pages as usize];
+    used[header.get_stream_block_map() as usize] = true;
+    for pfn in stream_directory.view.pages.pfns.iter() {
+        used[*pfn as usize] = true;
+    }
+    for stream in stream_directory.streams.iter() {
+        for pfn in stream.view.pages.pfns.iter() {
+            used[*pfn as usize] = true;
+        }
+    }
+    let fpm_start = (header.get_free_page_map() * page_size) as usize;
+    let fpm = &msf.bytes[fpm_start..fpm_start + page_size as usize];
+    for pfn in 0..num_pages {
+        let free = fpm[(pfn / 8) as usize] & (1 << (pfn % 8)) != 0;
+        let used = used[pfn as usize] || is_reserved_page(pfn, page_size);
+        assert_eq!(free, !used, "page {pfn:#x} has the wrong FPM bit");
+    }
+    // Bits past the end of the file are free.
+    assert_eq!(fpm[(num_pages / 8) as usize + 1], 0xFF);
+}
@@ -0,0 +1,37 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::path::Path;
+
+#[path = "../fuzz/src/lib.rs"]
+mod targets;
+
+/// Run every input in "fuzz/<kind>/<target>" through its target.
+fn replay(kind: &str) -> usize {
+    let mut inputs = 0;
+    for (name, target) in targets::TARGETS {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fuzz")
+            .join(kind)
+            .join(name);
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries {
+            target(&std::fs::read(entry.unwrap().path()).unwrap());
+            inputs += 1;
+        }
+    }
+    inputs
+}
+
+/// Every target accepts the seed corpus, see "examples/fuzz_corpus.rs".
+#[test]
+fn fuzz_test1() {
+    assert!(replay("corpus") > 0);
+}
+
+/// Crashes the fuzzer found stay fixed.
+#[test]
+fn fuzz_test2() {
+    assert!(replay("regressions") > 0);
+}
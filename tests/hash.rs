@@ -0,0 +1,147 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use elderscroll::{
+    directory::{IPI_STREAM_INDEX, TPI_STREAM_INDEX},
+    hash::{crc32, hash_buffer_v8, hash_string_v1, hash_string_v2},
+    msf::BigMsf,
+    pdbinfo::PdbInfoStream,
+    tpi::TpiStreamHeaderOverlay,
+};
+
+/// Does a linear probe from the bucket of "hash" reach "bucket" before it
+/// reaches an empty bucket?
+fn probes_to(hash: u32, bucket: u32, capacity: u32, present: impl Fn(u32) -> bool) -> bool {
+    let mut probe = hash % capacity;
+    while probe != bucket {
+        if !present(probe) {
+            return false;
+        }
+        probe = (probe + 1) % capacity;
+    }
+    true
+}
+
+/// Every name in the named stream map and the "/names" string table of
+/// HelloWorld.pdb is in the bucket hashStringV1 puts it in.
+#[test]
+fn hash_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let stream_directory = BigMsf::new(bytes.to_vec()).get_stream_directory().unwrap();
+    let info = PdbInfoStream::from_stream_directory(&stream_directory).unwrap();
+    let map = &info.named_stream_map;
+    for (bucket, (key, _)) in map.buckets.iter() {
+        let name = &map.strings[*key as usize..];
+        let name = &name[..name.iter().position(|byte| *byte == 0).unwrap()];
+        let hash = hash_string_v1(name) as u16 as u32;
+        let present = |bucket| map.buckets.contains_key(&bucket);
+        assert!(probes_to(hash, *bucket, map.capacity, present));
+    }
+
+    // https://llvm.org/docs/PDB/StringTable.html
+    let names = map.get("/names").unwrap() as usize;
+    let names = &stream_directory.streams[names].view.bytes;
+    let u32_at = |offset: usize| u32::from_le_bytes(names[offset..offset + 4].try_into().unwrap());
+    assert_eq!(u32_at(0), 0xEFFEEFFE);
+    assert_eq!(u32_at(4), 1);
+    let strings = &names[0xC..0xC + u32_at(8) as usize];
+    let capacity = u32_at(0xC + strings.len());
+    let buckets = (0..capacity)
+        .map(|bucket| u32_at(0x10 + strings.len() + bucket as usize * 4))
+        .collect::<Vec<u32>>();
+    let mut count = 0;
+    for (bucket, offset) in buckets.iter().enumerate() {
+        if *offset == 0 {
+            continue;
+        }
+        let string = &strings[*offset as usize..];
+        let string = &string[..string.iter().position(|byte| *byte == 0).unwrap()];
+        let present = |bucket: u32| buckets[bucket as usize] != 0;
+        assert!(probes_to(
+            hash_string_v1(string),
+            bucket as u32,
+            capacity,
+            present
+        ));
+        count += 1;
+    }
+    assert_eq!(count, u32_at(0x10 + strings.len() + buckets.len() * 4));
+}
+
+/// Every hash in the TPI and IPI hash streams of HelloWorld.pdb matches the
+/// hash LLVM documents for its type record.
+#[test]
+fn hash_test2() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let stream_directory = BigMsf::new(bytes.to_vec()).get_stream_directory().unwrap();
+    for index in [TPI_STREAM_INDEX, IPI_STREAM_INDEX] {
+        let stream = &stream_directory.streams[index].view.bytes;
+        let header = TpiStreamHeaderOverlay::new(stream).unwrap();
+        let hash_stream = &stream_directory.streams[header.get_hash_stream_index() as usize]
+            .view
+            .bytes;
+        let offset = header.get_hash_value_buffer_offset() as usize;
+        let mut hashes = hash_stream
+            [offset..offset + header.get_hash_value_buffer_length() as usize]
+            .chunks_exact(4)
+            .map(|hash| u32::from_le_bytes(hash.try_into().unwrap()));
+        let num_buckets = header.get_num_hash_buckets();
+        let mut offset = header.get_header_size() as usize;
+        while offset < stream.len() {
+            let len = u16::from_le_bytes([stream[offset], stream[offset + 1]]) as usize;
+            let record = &stream[offset..offset + 2 + len];
+            offset += 2 + len;
+            let kind = u16::from_le_bytes([record[2], record[3]]);
+            let hash = match kind {
+                // LF_CLASS, LF_STRUCTURE, LF_UNION and LF_ENUM hash their
+                // name unless they are forward references or anonymous.
+                0x1504..=0x1507 => {
+                    let property = u16::from_le_bytes([record[6], record[7]]);
+                    let name = match kind {
+                        0x1504 | 0x1505 => skip_numeric(record, 0x14),
+                        0x1506 => skip_numeric(record, 0xC),
+                        _ => 0x10,
+                    };
+                    let name = &record[name..];
+                    let name = &name[..name.iter().position(|byte| *byte == 0).unwrap()];
+                    if property & 0x80 == 0 && name != b"<unnamed-tag>" {
+                        hash_string_v1(name)
+                    } else {
+                        hash_buffer_v8(record)
+                    }
+                }
+                // LF_UDT_SRC_LINE and LF_UDT_MOD_SRC_LINE hash the UDT index.
+                0x1606 | 0x1607 => hash_string_v1(&record[4..8]),
+                _ => hash_buffer_v8(record),
+            };
+            assert_eq!(hash % num_buckets, hashes.next().unwrap());
+        }
+        assert!(hashes.next().is_none());
+    }
+
+    // Check values of the functions no table in HelloWorld.pdb uses as is.
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    assert_eq!(hash_buffer_v8(b""), 0);
+    assert_eq!(
+        hash_string_v2(b""),
+        0xB170A1BFu32.wrapping_mul(1664525) + 1013904223
+    );
+    assert_eq!(hash_string_v1(b"/NAMES"), hash_string_v1(b"/names"));
+    assert_ne!(hash_string_v2(b"/NAMES"), hash_string_v2(b"/names"));
+}
+
+/// Offset past a numeric leaf at "offset" of a type record.
+fn skip_numeric(record: &[u8], offset: usize) -> usize {
+    match u16::from_le_bytes([record[offset], record[offset + 1]]) {
+        0x8000 => offset + 3,
+        0x8001 | 0x8002 => offset + 4,
+        0x8003 | 0x8004 => offset + 6,
+        0x8009 | 0x800A => offset + 10,
+        _ => offset + 2,
+    }
+}
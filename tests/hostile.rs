@@ -0,0 +1,205 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::io::Cursor;
+
+use elderscroll::{
+    builder::PdbBuilder,
+    dbi::DbiStream,
+    directory::{StreamDirectory, DBI_STREAM_INDEX},
+    error::Error,
+    msf::{BigMsf, BLOCK_MAP_OFFSET},
+    msfz::{Compression, Msfz, MsfzChunkOverlayMut, MsfzHeaderMut, FRAGMENT_IN_CHUNK},
+    paged::PagedMsf,
+    smallmsf::SmallMsf,
+    source::BytesSource,
+};
+
+/// Everything a tool does with an untrusted file, none of it may panic.
+fn open_everything(bytes: &[u8]) {
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let _ = msf.verify();
+    #[cfg(feature = "pdb")]
+    let _ = msf.to_pdb_source();
+    if let Ok(stream_directory) = msf.get_stream_directory() {
+        if let Some(stream) = stream_directory.streams.get(DBI_STREAM_INDEX) {
+            let mut dbi = DbiStream::new(stream.clone());
+            let _ = dbi.extra_streams();
+            #[allow(deprecated)]
+            let _ = dbi.nop_section_maps();
+            let _ = dbi.rebuild_section_map(&stream_directory);
+        }
+        let _ = BigMsf::new(bytes.to_vec()).commit_stream_directory(stream_directory.clone());
+        let _ = msf.set_stream_directory(stream_directory);
+    }
+    let _ = BigMsf::new(bytes.to_vec()).compact();
+    if let Ok(mut paged) = PagedMsf::open(BytesSource(bytes.to_vec())) {
+        let _ = paged.stream_mut(DBI_STREAM_INDEX);
+        let _ = paged.flush(&mut Cursor::new(bytes.to_vec()));
+    }
+    let _ = SmallMsf::new(bytes.to_vec()).get_stream_directory();
+    let _ = Msfz::new(bytes.to_vec()).get_stream_directory();
+}
+
+/// Truncated files and every header and directory field set to hostile values.
+#[test]
+fn hostile_test1() {
+    let hello_world = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let msf = PdbBuilder::new().page_size(0x200).build().unwrap();
+    for len in (0..msf.bytes.len()).step_by(0x80) {
+        open_everything(&msf.bytes[..len]);
+    }
+    for msf in [msf, BigMsf::new(hello_world.to_vec())] {
+        let page_size = msf.header().unwrap().get_page_size() as usize;
+        let stream_directory = msf.get_stream_directory().unwrap();
+        let dir_page = stream_directory.view.pages.pfns[0] as usize * page_size;
+        let dbi_page =
+            stream_directory.streams[DBI_STREAM_INDEX].view.pages.pfns[0] as usize * page_size;
+        for offset in (0..BLOCK_MAP_OFFSET + 4)
+            .chain(dir_page..dir_page + 0x40)
+            .chain(dbi_page..dbi_page + 0x40)
+            .step_by(4)
+        {
+            for value in [0, 1, 0x7FFF_FFFF, 0x8000_0000, u32::MAX] {
+                let mut bytes = msf.bytes.clone();
+                bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+                open_everything(&bytes);
+            }
+        }
+    }
+}
+
+/// Files which claim far more than they hold are rejected up front.
+#[test]
+#[allow(deprecated)]
+fn hostile_test2() {
+    let msf = PdbBuilder::new().build().unwrap();
+    let page_size = msf.header().unwrap().get_page_size();
+    let num_pages = msf.header().unwrap().get_num_pages();
+
+    // One stream which uses the same page over and over.
+    let mut bytes = msf.bytes.clone();
+    let (dir_page, block_map_page) = (num_pages, num_pages + 1);
+    let repeats = page_size / 4 - 2;
+    let mut directory = vec![1u8, 0, 0, 0];
+    directory.extend_from_slice(&(repeats * page_size).to_le_bytes());
+    for _ in 0..repeats {
+        directory.extend_from_slice(&1u32.to_le_bytes());
+    }
+    directory.resize(page_size as usize, 0);
+    bytes.extend_from_slice(&directory);
+    let mut block_map = dir_page.to_le_bytes().to_vec();
+    block_map.resize(page_size as usize, 0);
+    bytes.extend_from_slice(&block_map);
+    let mut msf = BigMsf::new(bytes);
+    let mut header = msf.header_mut().unwrap();
+    header.set_num_pages(num_pages + 2);
+    header.set_stream_dir_size(directory.len() as u32);
+    msf.bytes[BLOCK_MAP_OFFSET..BLOCK_MAP_OFFSET + 4]
+        .copy_from_slice(&block_map_page.to_le_bytes());
+    assert!(matches!(
+        msf.get_stream_directory(),
+        Err(Error::TooManyPages { pages, num_pages: _ }) if pages == repeats as u64
+    ));
+
+    // A header with far more pages than the file still saves.
+    let mut msf = PdbBuilder::new().build().unwrap();
+    let stream_directory = msf.get_stream_directory().unwrap();
+    msf.header_mut().unwrap().set_num_pages(u32::MAX);
+    msf.set_stream_directory(stream_directory).unwrap();
+    assert!(msf.header().unwrap().get_num_pages() < num_pages * 2);
+
+    // An FPM page other than 1 or 2 is refused before anything is written.
+    for fpm in [0, 3, 0x8000, u32::MAX] {
+        let mut msf = PdbBuilder::new().build().unwrap();
+        msf.header_mut().unwrap().set_free_page_map(fpm);
+        let mut paged = PagedMsf::open(BytesSource(msf.bytes.clone())).unwrap();
+        paged.stream_mut(DBI_STREAM_INDEX).unwrap();
+        let mut out = Cursor::new(msf.bytes.clone());
+        assert!(matches!(
+            paged.flush(&mut out),
+            Err(Error::BadFreePageMap(bad)) if bad == fpm
+        ));
+        assert_eq!(out.into_inner(), msf.bytes);
+    }
+
+    // Every substream of the DBI stream claims 4 GB.
+    let msf = PdbBuilder::new().build().unwrap();
+    let stream_directory = msf.get_stream_directory().unwrap();
+    let mut dbi = DbiStream::new(stream_directory.streams[DBI_STREAM_INDEX].clone());
+    let mut header = dbi.header_mut().unwrap();
+    header.set_mod_info_size(u32::MAX);
+    header.set_section_contribution_size(u32::MAX);
+    header.set_section_map_size(u32::MAX);
+    header.set_optional_dbg_header_size(u32::MAX);
+    assert!(matches!(
+        dbi.extra_streams(),
+        Err(Error::SubstreamOutOfBounds { .. })
+    ));
+    assert!(dbi.nop_section_maps().is_err());
+    assert!(matches!(
+        dbi.section_map(),
+        Err(Error::SubstreamOutOfBounds { .. })
+    ));
+
+    // Fragments of an MSFZ file which all point at the same bytes.
+    let mut stream_directory = StreamDirectory::default();
+    stream_directory.add_stream(vec![0x69; 0x1000]);
+    let mut bytes = Msfz::from_stream_directory(&stream_directory, Compression::None)
+        .unwrap()
+        .bytes;
+    let mut directory = Vec::new();
+    for _ in 0..0x100 {
+        for value in [0x1000, 0, FRAGMENT_IN_CHUNK] {
+            directory.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    directory.extend_from_slice(&0u32.to_le_bytes());
+    let dir_offset = bytes.len() as u64;
+    bytes.extend_from_slice(&directory);
+    let mut header = MsfzHeaderMut::new(&mut bytes).unwrap();
+    header.set_stream_dir_offset(dir_offset);
+    header.set_stream_dir_compression(Compression::None.to_u32());
+    header.set_stream_dir_size_compressed(directory.len() as u32);
+    header.set_stream_dir_size_uncompressed(directory.len() as u32);
+    assert!(matches!(
+        Msfz::new(bytes).get_stream_directory(),
+        Err(Error::TooManyBytes { .. })
+    ));
+
+    // A chunk no stream uses is never decompressed, however big it claims to be.
+    let mut bytes = Msfz::from_stream_directory(&stream_directory, Compression::Zstd)
+        .unwrap()
+        .bytes;
+    let header = MsfzHeaderMut::new(&mut bytes).unwrap();
+    let chunk_table_offset = header.get_chunk_table_offset() as usize;
+    let mut chunk_table = bytes[chunk_table_offset..].to_vec();
+    let mut unused = vec![0u8; chunk_table.len()];
+    let mut entry = MsfzChunkOverlayMut::new(&mut unused).unwrap();
+    entry.set_file_offset(0);
+    entry.set_compression(Compression::Zstd.to_u32());
+    entry.set_compressed_size(0x10);
+    entry.set_uncompressed_size(u32::MAX);
+    chunk_table.extend_from_slice(&unused);
+    bytes.truncate(chunk_table_offset);
+    bytes.extend_from_slice(&chunk_table);
+    let mut header = MsfzHeaderMut::new(&mut bytes).unwrap();
+    header.set_num_chunks(2);
+    header.set_chunk_table_size(chunk_table.len() as u32);
+    let msfz = Msfz::new(bytes.clone());
+    assert_eq!(
+        msfz.get_stream_directory().unwrap().streams[0].view.bytes,
+        vec![0x69; 0x1000]
+    );
+
+    // A used chunk which claims more than its bytes could ever hold is refused
+    // before anything is decompressed.
+    let mut entry = MsfzChunkOverlayMut::new(&mut bytes[chunk_table_offset..]).unwrap();
+    entry.set_uncompressed_size(u32::MAX);
+    assert!(matches!(
+        Msfz::new(bytes).get_stream_directory(),
+        Err(Error::TooManyBytes { .. })
+    ));
+}
@@ -0,0 +1,135 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::io::Cursor;
+
+use elderscroll::{
+    dbi::DbiStream,
+    directory::INVALID_STREAM_INDEX,
+    error::Error,
+    modinfo::{ModuleInfo, ModuleInfoIter},
+    msf::BigMsf,
+};
+use pdb::FallibleIterator;
+
+/// Read the modules of HelloWorld.pdb, they match the pdb crate's and write
+/// back the same module info substream.
+#[test]
+fn modinfo_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let stream_directory = BigMsf::new(bytes.to_vec()).get_stream_directory().unwrap();
+    let dbi = DbiStream::from_stream_directory(&stream_directory).unwrap();
+    let modules = dbi.modules().unwrap();
+    let mut pdb = pdb::PDB::open(Cursor::new(bytes.to_vec())).unwrap();
+    let debug_information = pdb.debug_information().unwrap();
+    let expected = debug_information
+        .modules()
+        .unwrap()
+        .collect::<Vec<_>>()
+        .unwrap();
+    assert_eq!(modules.len(), expected.len());
+    for (index, (module, expected)) in modules.iter().zip(expected.iter()).enumerate() {
+        assert_eq!(module.module_name_lossy(), expected.module_name());
+        assert_eq!(module.object_name_lossy(), expected.object_file_name());
+        // The symbol stream holds the symbols and line info.
+        if let Some(stream) = module.symbol_stream() {
+            let size = stream_directory.streams[stream].view.bytes.len() as u32;
+            let sizes = module.sym_byte_size + module.c11_byte_size + module.c13_byte_size;
+            assert!(sizes <= size);
+            assert!(pdb.module_info(expected).unwrap().is_some());
+        }
+        let section_contribution = module.section_contribution;
+        if section_contribution.section != 0xFFFF {
+            assert_eq!(section_contribution.module_index as usize, index);
+        }
+    }
+    let exe_main = &modules[1];
+    assert!(exe_main.module_name.ends_with(b"exe_main.obj"));
+    assert!(exe_main.object_name.ends_with(b"MSVCRT.lib"));
+    assert_eq!(exe_main.section_contribution.section, 1);
+    assert_eq!(exe_main.section_contribution.size, 182);
+    assert_eq!(exe_main.symbol_stream(), Some(53));
+    assert_eq!(exe_main.source_file_count, 143);
+    let mut substreams = dbi.substreams().unwrap();
+    let module_info = substreams.module_info.clone();
+    substreams.set_modules(&modules).unwrap();
+    assert_eq!(substreams.module_info, module_info);
+}
+
+/// Rename an object file and move a module to a new symbol stream, the pdb
+/// crate reads the module's symbols from the new stream.
+#[test]
+fn modinfo_test2() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let mut dbi = DbiStream::from_stream_directory(&stream_directory).unwrap();
+    let mut modules = dbi.modules().unwrap();
+    let old_stream = modules[1].symbol_stream().unwrap();
+    let symbols = stream_directory.streams[old_stream].view.bytes.clone();
+    let new_stream = stream_directory.add_stream(symbols);
+    modules[1].set_symbol_stream(Some(new_stream)).unwrap();
+    modules[1].object_name = b"C:\\remapped\\a\\much\\longer\\path\\to\\MSVCRT.lib".to_vec();
+    // Names are in the ANSI code page, "\xE9" is an "e" with an accent.
+    modules[2].module_name = b"C:\\caf\xE9\\gs.obj".to_vec();
+    stream_directory.remove_stream(old_stream).unwrap();
+    dbi.set_modules(&modules).unwrap();
+    dbi.flush(&mut stream_directory).unwrap();
+    msf.set_stream_directory(stream_directory).unwrap();
+
+    let stream_directory = msf.get_stream_directory().unwrap();
+    let dbi = DbiStream::from_stream_directory(&stream_directory).unwrap();
+    assert_eq!(dbi.modules().unwrap(), modules);
+    let mut pdb = pdb::PDB::open(Cursor::new(msf.bytes)).unwrap();
+    let debug_information = pdb.debug_information().unwrap();
+    let expected = debug_information
+        .modules()
+        .unwrap()
+        .collect::<Vec<_>>()
+        .unwrap();
+    assert_eq!(
+        expected[1].object_file_name(),
+        modules[1].object_name_lossy()
+    );
+    assert_eq!(expected[2].module_name(), "C:\\caf\u{FFFD}\\gs.obj");
+    let module_info = pdb.module_info(&expected[1]).unwrap().unwrap();
+    assert!(module_info.symbols().unwrap().count().unwrap() > 0);
+
+    let mut module = ModuleInfo::default();
+    assert!(matches!(
+        module.set_symbol_stream(Some(INVALID_STREAM_INDEX as usize)),
+        Err(Error::StreamIndexTooLarge(_))
+    ));
+    module.set_symbol_stream(None).unwrap();
+    assert_eq!(module.symbol_stream(), None);
+
+    // Unused fields and padding are written back as they were read.
+    module.unused1 = 0x69696969;
+    module.section_contribution.padding1 = 0x4242;
+    module.section_contribution.padding2 = 0x1337;
+    module.padding = 0xDEAD;
+    module.unused2 = 0xBEEF;
+    let mut bytes = Vec::new();
+    module.write(&mut bytes).unwrap();
+    let mut offset = 0;
+    let read = ModuleInfo::read(&bytes, &mut offset).unwrap();
+    assert_eq!(read, module);
+    let mut written = Vec::new();
+    read.write(&mut written).unwrap();
+    assert_eq!(written, bytes);
+
+    // Truncated modules end the iterator with an error, the padding after the
+    // names is optional.
+    assert_eq!(bytes.len(), 0x44);
+    assert_eq!(ModuleInfoIter::new(&bytes[..0x42]).count(), 1);
+    for len in 1..0x42 {
+        let mut modules = ModuleInfoIter::new(&bytes[..len]);
+        assert!(modules.next().unwrap().is_err());
+        assert!(modules.next().is_none());
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use elderscroll::{
+    directory::INVALID_STREAM_SIZE,
+    msf::BigMsf,
+    msfz::{Compression, Msfz, MSFZ_CHUNK_SIZE},
+};
+
+/// Write HelloWorld.pdb as MSFZ with every compression and read it back.
+#[test]
+fn msfz_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let msf = BigMsf::new(bytes.to_vec());
+    let stream_directory = msf.get_stream_directory().unwrap();
+    for compression in [Compression::None, Compression::Zstd, Compression::Deflate] {
+        let msfz = msf.to_msfz(compression).unwrap();
+        if compression != Compression::None {
+            assert!(msfz.bytes.len() < bytes.len());
+        }
+        let msfz_directory = msfz.get_stream_directory().unwrap();
+        assert_eq!(msfz_directory.streams.len(), stream_directory.streams.len());
+        for (before, after) in stream_directory
+            .streams
+            .iter()
+            .zip(msfz_directory.streams.iter())
+        {
+            assert_eq!(before.view.bytes, after.view.bytes);
+            if before.original_stream_size == INVALID_STREAM_SIZE {
+                assert_eq!(after.original_stream_size, INVALID_STREAM_SIZE);
+            }
+        }
+        // Open it like any other PDB, the streams must be the same.
+        let opened = BigMsf::open(msfz.bytes).unwrap();
+        let opened_directory = opened.get_stream_directory().unwrap();
+        for (before, after) in stream_directory
+            .streams
+            .iter()
+            .zip(opened_directory.streams.iter())
+        {
+            assert_eq!(before.view.bytes, after.view.bytes);
+        }
+    }
+}
+
+/// Streams which do not fit in one chunk are split into several fragments.
+#[test]
+fn msfz_test2() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let msf = BigMsf::new(bytes.to_vec());
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let big = (0..MSFZ_CHUNK_SIZE * 2 + 0x123)
+        .map(|i| (i % 253) as u8)
+        .collect::<Vec<_>>();
+    stream_directory.streams[1].view.bytes = big.clone();
+    let msfz = Msfz::from_stream_directory(&stream_directory, Compression::Zstd).unwrap();
+    assert_eq!(msfz.header().unwrap().get_num_chunks(), 3);
+    let msfz_directory = msfz.get_stream_directory().unwrap();
+    assert_eq!(msfz_directory.streams[1].view.bytes, big);
+    // Truncated files must fail to parse.
+    let truncated = Msfz::new(msfz.bytes[..msfz.bytes.len() - 1].to_vec());
+    assert!(truncated.get_stream_directory().is_err());
+    assert!(BigMsf::open(vec![0u8; 0x100]).is_err());
+}
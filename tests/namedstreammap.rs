@@ -0,0 +1,97 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::io::Cursor;
+
+use elderscroll::{
+    builder::PdbBuilder, directory::PDB_INFO_STREAM_INDEX, error::Error, msf::BigMsf,
+    namedstreammap::NamedStreamMap, pdbinfo::PdbInfoStream,
+};
+
+/// Parse the named stream map of HelloWorld.pdb and write back the same bytes.
+#[test]
+fn namedstreammap_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let msf = BigMsf::new(bytes.to_vec());
+    let stream_directory = msf.get_stream_directory().unwrap();
+    let stream = &stream_directory.streams[PDB_INFO_STREAM_INDEX].view.bytes;
+    let mut offset = 0x1C;
+    let map = NamedStreamMap::read(stream, &mut offset).unwrap();
+    assert_eq!(map.capacity, 10);
+    assert_eq!(map.get("/LinkInfo"), Some(5));
+    assert_eq!(map.get("/TMCache"), Some(6));
+    assert_eq!(map.get("/names"), Some(7));
+    assert_eq!(map.get("/natvis"), None);
+    assert_eq!(map.buckets.get(&5).unwrap().1, 7);
+    assert_eq!(map.iter().count(), 6);
+    assert_eq!(map.to_vec().unwrap(), stream[0x1C..offset]);
+}
+
+/// Insert enough names for the table to grow, remove some and save the PDB,
+/// every name is still found by elderscroll and by the pdb crate.
+#[test]
+fn namedstreammap_test2() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let mut info = PdbInfoStream::from_stream_directory(&stream_directory).unwrap();
+    let names = (0..40)
+        .map(|i| format!("/natvis/{i}"))
+        .collect::<Vec<String>>();
+    for name in names.iter() {
+        let index = stream_directory.add_stream(name.as_bytes().to_vec());
+        assert_eq!(info.named_stream_map.insert(name, index as u32), None);
+    }
+    assert!(info.named_stream_map.capacity > 10);
+    assert_eq!(info.named_stream_map.insert("/names", 7), Some(7));
+    for name in names.iter().step_by(2) {
+        assert!(info.named_stream_map.remove(name).is_some());
+        assert_eq!(info.named_stream_map.remove(name), None);
+    }
+    info.flush(&mut stream_directory).unwrap();
+    msf.set_stream_directory(stream_directory).unwrap();
+
+    let stream_directory = msf.get_stream_directory().unwrap();
+    let map = PdbInfoStream::from_stream_directory(&stream_directory)
+        .unwrap()
+        .named_stream_map;
+    assert_eq!(map, info.named_stream_map);
+    assert_eq!(map.get("/names"), Some(7));
+    for (i, name) in names.iter().enumerate() {
+        let index = map.get(name);
+        assert_eq!(index.is_none(), i % 2 == 0);
+        if let Some(index) = index {
+            assert_eq!(
+                stream_directory.streams[index as usize].view.bytes,
+                name.as_bytes()
+            );
+        }
+    }
+    let mut pdb = pdb::PDB::open(Cursor::new(msf.bytes)).unwrap();
+    pdb.string_table().unwrap();
+
+    // Bits past the capacity and sizes which do not match are errors.
+    let bytes = map.to_vec().unwrap();
+    for len in 0..bytes.len() {
+        assert!(NamedStreamMap::read(&bytes[..len], &mut 0).is_err());
+    }
+    let mut bytes = NamedStreamMap::default().to_vec().unwrap();
+    bytes[4] = 1;
+    let result = NamedStreamMap::read(&bytes, &mut 0);
+    assert!(matches!(result, Err(Error::BadHashTable { .. })));
+    bytes[4] = 0;
+    bytes[0x10] = 0x80;
+    let result = NamedStreamMap::read(&bytes, &mut 0);
+    assert!(matches!(result, Err(Error::BadHashTable { .. })));
+
+    // PdbBuilder writes a map that only has "/names".
+    let msf = PdbBuilder::new().build().unwrap();
+    let info = PdbInfoStream::from_stream_directory(&msf.get_stream_directory().unwrap());
+    let map = info.unwrap().named_stream_map;
+    assert_eq!(map.iter().collect::<Vec<_>>(), vec![("/names", 5)]);
+}
@@ -4,11 +4,9 @@ use std::io::Write;
 
 use elderscroll::{
     dbi::DbiStream,
-    directory::{Stream, DBI_STREAM_INDEX, INVALID_STREAM_SIZE},
+    directory::{DBI_STREAM_INDEX, INVALID_STREAM_SIZE},
     msf::BigMsf,
     omap::{OmapEntry, OmapStream},
-    pagelist::PageList,
-    view::SourceView,
 };
 
 /// This test just moves 2 functions to padding inbetween
@@ -23,47 +21,29 @@ fn omap_test1() {
         "/tests/bins/HelloWorld.pdb"
     ));
     let mut msf = BigMsf::new(bytes.to_vec());
-    let page_size = msf.header().unwrap().get_page_size();
     let mut stream_directory = msf.get_stream_directory().unwrap();
-    let dbi_stream = stream_directory.streams[DBI_STREAM_INDEX].clone();
+    let dbi_stream = stream_directory.stream(DBI_STREAM_INDEX).unwrap().clone();
     assert!(dbi_stream.original_stream_size != INVALID_STREAM_SIZE);
     let mut dbi = DbiStream::new(dbi_stream);
+    #[allow(deprecated)]
     dbi.nop_section_maps().unwrap();
-    // Set original section headers the same as the "section headers" stream.
-    let mut extras = dbi.extra_streams_mut().unwrap();
-    extras.set_original_section_headers(extras.get_section_headers());
-    // Set the omap to src.
-    let omap_stream_index = stream_directory.streams.len();
-    extras.set_omap_to_src(omap_stream_index as u16);
     let mut omap_stream = OmapStream::default();
     omap_stream.0.insert(OmapEntry(0x1008, 0x1000));
     omap_stream.0.insert(OmapEntry(0x100B, 0x1000));
     omap_stream.0.insert(OmapEntry(0x100E, 0x1000));
     omap_stream.0.insert(OmapEntry(0x1088, 0x1000));
     omap_stream.0.insert(OmapEntry(0x109F, 0x109F));
-    let omap_bytes = omap_stream.to_vec().unwrap();
-    // Omap to src.
-    stream_directory.streams.push(Stream {
-        original_stream_size: Default::default(),
-        view: SourceView {
-            bytes: omap_bytes,
-            pages: PageList::new(page_size),
-        },
-    });
-    // Omap from src
-    let omap_stream_index2 = stream_directory.streams.len();
-    extras.set_omap_from_src(omap_stream_index2 as u16);
     let mut omap_stream2 = OmapStream::default();
     omap_stream2.0.insert(OmapEntry(0x7000, 0x0));
-    let omap_bytes2 = omap_stream2.to_vec().unwrap();
-    stream_directory.streams.push(Stream {
-        original_stream_size: Default::default(),
-        view: SourceView {
-            bytes: omap_bytes2,
-            pages: PageList::new(page_size),
-        },
-    });
-    stream_directory.streams[DBI_STREAM_INDEX] = dbi.stream;
+    // New OMAP streams, the "section headers" stream becomes the original
+    // section headers.
+    dbi.set_omap(&mut stream_directory, &omap_stream, &omap_stream2)
+        .unwrap();
+    dbi.flush(&mut stream_directory).unwrap();
+    assert_eq!(
+        dbi.omap(&stream_directory).unwrap(),
+        (Some(omap_stream), Some(omap_stream2))
+    );
     msf.set_stream_directory(stream_directory).unwrap();
     let header = msf.header().unwrap();
     assert_eq!(
@@ -71,10 +51,19 @@ fn omap_test1() {
         msf.bytes.len() as u32
     );
     // Save file.
-    let mut f1 = std::fs::File::create(concat!(
-        env!("CARGO_MANIFEST_DIR"),
-        "/tests/bins/HelloWorld_new.pdb"
-    ))
-    .unwrap();
+    let mut f1 =
+        std::fs::File::create(concat!(env!("CARGO_TARGET_TMPDIR"), "/HelloWorld_new.pdb")).unwrap();
     f1.write_all(&msf.bytes).unwrap();
 }
+
+/// OMAP streams read back the entries they were written with.
+#[test]
+fn omap_test2() {
+    let mut omap_stream = OmapStream::default();
+    omap_stream.0.insert(OmapEntry(0x1008, 0x1000));
+    omap_stream.0.insert(OmapEntry(0x7000, 0x0));
+    let bytes = omap_stream.to_vec().unwrap();
+    assert_eq!(OmapStream::new(&bytes).unwrap().0, omap_stream.0);
+    assert!(OmapStream::new(&[]).unwrap().0.is_empty());
+    assert!(OmapStream::new(&bytes[..bytes.len() - 1]).is_err());
+}
@@ -0,0 +1,200 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{Cursor, Seek, SeekFrom, Write},
+};
+
+use elderscroll::{
+    directory::DBI_STREAM_INDEX,
+    error::Error,
+    msf::{BigMsf, SyncWrite},
+    paged::PagedMsf,
+    source::{BytesSource, MmapSource, PageSource, ReadSeekSource},
+};
+
+/// Source which remembers which offsets were read.
+struct CountingSource {
+    source: BytesSource<Vec<u8>>,
+    reads: BTreeSet<u64>,
+}
+
+impl PageSource for CountingSource {
+    fn len(&self) -> u64 {
+        self.source.len()
+    }
+    fn read_at(&mut self, offset: u64, buff: &mut [u8]) -> Result<(), Error> {
+        self.reads.insert(offset);
+        self.source.read_at(offset, buff)
+    }
+}
+
+/// Writer which remembers which offsets were written.
+struct CountingWriter {
+    file: Cursor<Vec<u8>>,
+    writes: BTreeSet<u64>,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writes.insert(self.file.position());
+        self.file.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for CountingWriter {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl SyncWrite for CountingWriter {
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.file.sync()
+    }
+}
+
+/// Streams are only read when touched and only changed streams are written.
+#[test]
+fn paged_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let expected = BigMsf::new(bytes.to_vec()).get_stream_directory().unwrap();
+    let mut msf = PagedMsf::open(CountingSource {
+        source: BytesSource(bytes.to_vec()),
+        reads: BTreeSet::new(),
+    })
+    .unwrap();
+    assert_eq!(msf.num_streams(), expected.streams.len());
+    let dbi_pages = expected.streams[DBI_STREAM_INDEX].view.pages.pfns.clone();
+    let page_size = msf.header().unwrap().get_page_size() as u64;
+    assert!(dbi_pages
+        .iter()
+        .all(|pfn| !msf.source.reads.contains(&(*pfn as u64 * page_size))));
+    assert!(!msf.is_loaded(DBI_STREAM_INDEX));
+    assert_eq!(
+        msf.stream(DBI_STREAM_INDEX).unwrap().view.bytes,
+        expected.streams[DBI_STREAM_INDEX].view.bytes
+    );
+    assert!(msf.is_loaded(DBI_STREAM_INDEX));
+    assert!(dbi_pages
+        .iter()
+        .all(|pfn| msf.source.reads.contains(&(*pfn as u64 * page_size))));
+    assert!(msf.stream(expected.streams.len()).is_err());
+
+    // Grow the DBI stream, nothing else is written except the directory.
+    msf.stream_mut(DBI_STREAM_INDEX).unwrap().view.bytes.resize(
+        expected.streams[DBI_STREAM_INDEX].view.bytes.len() + 0x3000,
+        0x69,
+    );
+    let mut out = CountingWriter {
+        file: Cursor::new(bytes.to_vec()),
+        writes: BTreeSet::new(),
+    };
+    msf.flush(&mut out).unwrap();
+    let untouched = expected
+        .streams
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *index != DBI_STREAM_INDEX)
+        .flat_map(|(_, stream)| stream.view.pages.pfns.iter())
+        .map(|pfn| *pfn as u64 * page_size)
+        .collect::<BTreeSet<u64>>();
+    assert!(out.writes.is_disjoint(&untouched));
+
+    let written = BigMsf::new(out.file.into_inner());
+    assert_eq!(written.verify(), vec![]);
+    let written_dir = written.get_stream_directory().unwrap();
+    for (index, (expected, written)) in expected
+        .streams
+        .iter()
+        .zip(written_dir.streams.iter())
+        .enumerate()
+    {
+        if index == DBI_STREAM_INDEX {
+            assert_eq!(written.view.bytes.len(), expected.view.bytes.len() + 0x3000);
+            assert_eq!(
+                written.view.bytes[..expected.view.bytes.len()],
+                expected.view.bytes
+            );
+        } else {
+            assert_eq!(written.view.bytes, expected.view.bytes);
+        }
+    }
+}
+
+/// Memory mapped files and readers give the same streams as "BigMsf".
+#[test]
+fn paged_test2() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let path = concat!(env!("CARGO_TARGET_TMPDIR"), "/paged_test2.pdb");
+    std::fs::write(path, bytes).unwrap();
+    let expected = BigMsf::new(bytes.to_vec()).get_stream_directory().unwrap();
+    let mut mapped = PagedMsf::open(MmapSource::map(&File::open(path).unwrap()).unwrap()).unwrap();
+    let mut read = PagedMsf::open(ReadSeekSource::new(File::open(path).unwrap()).unwrap()).unwrap();
+    for (index, stream) in expected.streams.iter().enumerate() {
+        assert_eq!(mapped.stream(index).unwrap().view.bytes, stream.view.bytes);
+        assert_eq!(read.stream(index).unwrap().view.bytes, stream.view.bytes);
+    }
+    // Flush into the same file the reader reads from.
+    let mut file = File::options().read(true).write(true).open(path).unwrap();
+    read.stream_mut(DBI_STREAM_INDEX).unwrap().view.bytes[0x40] ^= 0xFF;
+    read.flush(&mut file).unwrap();
+    let written = BigMsf::new(std::fs::read(path).unwrap());
+    assert_eq!(written.verify(), vec![]);
+    assert_eq!(
+        written.get_stream_directory().unwrap().streams[DBI_STREAM_INDEX]
+            .view
+            .bytes[0x40],
+        expected.streams[DBI_STREAM_INDEX].view.bytes[0x40] ^ 0xFF
+    );
+}
+
+/// Flushing twice into the same file, the second flush knows the file grew.
+#[test]
+fn paged_test3() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let expected = BigMsf::new(bytes.to_vec()).get_stream_directory().unwrap();
+    let mut msf = PagedMsf::open(BytesSource(bytes.to_vec())).unwrap();
+    let mut out = Cursor::new(bytes.to_vec());
+    for index in [DBI_STREAM_INDEX, 2] {
+        let stream = msf.stream_mut(index).unwrap();
+        let len = stream.view.bytes.len();
+        stream.view.bytes.resize(len + 0x80000, 0x69);
+        msf.flush(&mut out).unwrap();
+    }
+    let written = BigMsf::new(out.into_inner());
+    assert_eq!(written.verify(), vec![]);
+    let written_dir = written.get_stream_directory().unwrap();
+    for (index, (expected, written)) in expected
+        .streams
+        .iter()
+        .zip(written_dir.streams.iter())
+        .enumerate()
+    {
+        if index == DBI_STREAM_INDEX || index == 2 {
+            assert_eq!(
+                written.view.bytes.len(),
+                expected.view.bytes.len() + 0x80000
+            );
+            assert_eq!(
+                written.view.bytes[..expected.view.bytes.len()],
+                expected.view.bytes
+            );
+        } else {
+            assert_eq!(written.view.bytes, expected.view.bytes);
+        }
+    }
+}
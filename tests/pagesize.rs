@@ -0,0 +1,36 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use elderscroll::msf::{is_valid_page_size, BigMsf};
+
+/// Move every stream of HelloWorld.pdb into MSF files with big pages.
+#[test]
+fn pagesize_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let msf = BigMsf::new(bytes.to_vec());
+    let stream_directory = msf.get_stream_directory().unwrap();
+    for page_size in [0x2000u32, 0x4000, 0x8000] {
+        assert!(is_valid_page_size(page_size));
+        let big_msf = BigMsf::from_stream_directory(page_size, stream_directory.clone()).unwrap();
+        let header = big_msf.header().unwrap();
+        assert_eq!(header.get_page_size(), page_size);
+        assert_eq!(header.file_size(), big_msf.bytes.len() as u64);
+        assert_eq!(big_msf.verify(), vec![]);
+        let big_directory = big_msf.get_stream_directory().unwrap();
+        assert_eq!(big_directory.streams.len(), stream_directory.streams.len());
+        for (before, after) in stream_directory
+            .streams
+            .iter()
+            .zip(big_directory.streams.iter())
+        {
+            assert_eq!(before.view.bytes, after.view.bytes);
+            if !after.view.pages.is_empty() {
+                assert_eq!(after.view.pages.page_size, page_size);
+            }
+        }
+    }
+    assert!(!is_valid_page_size(0x10000));
+    assert!(BigMsf::from_stream_directory(0x10000, stream_directory).is_err());
+}
@@ -0,0 +1,86 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::io::Cursor;
+
+use elderscroll::{
+    builder::PdbBuilder,
+    dbi::DbiStream,
+    directory::{DBI_STREAM_INDEX, PDB_INFO_STREAM_INDEX},
+    msf::BigMsf,
+    pdbinfo::{PdbFeature, PdbInfoStream, PDB_INFO_VERSION_VC70},
+};
+
+const GUID: [u8; 16] = [
+    0x69, 0x42, 0x13, 0x37, 0xDE, 0xAD, 0xBE, 0xEF, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08,
+];
+
+/// Parse the PDB info stream of HelloWorld.pdb and write back the same bytes.
+#[test]
+fn pdbinfo_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let msf = BigMsf::new(bytes.to_vec());
+    let stream_directory = msf.get_stream_directory().unwrap();
+    let info = PdbInfoStream::from_stream_directory(&stream_directory).unwrap();
+    let mut pdb = pdb::PDB::open(Cursor::new(bytes.to_vec())).unwrap();
+    let expected = pdb.pdb_information().unwrap();
+    assert_eq!(info.version, PDB_INFO_VERSION_VC70);
+    assert_eq!(info.signature, expected.signature);
+    assert_eq!(info.age, expected.age);
+    assert_eq!(info.guid[8..], expected.guid.as_bytes()[8..]);
+    assert_eq!(info.features, vec![PdbFeature::Vc140]);
+    assert!(info.has_feature(PdbFeature::Vc140));
+    assert!(!info.has_feature(PdbFeature::MinimalDebugInfo));
+    assert_eq!(
+        info.to_vec().unwrap(),
+        stream_directory.streams[PDB_INFO_STREAM_INDEX].view.bytes
+    );
+}
+
+/// Stamp a new signature, age and GUID, the DBI stream gets the same age.
+#[test]
+fn pdbinfo_test2() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let mut info = PdbInfoStream::from_stream_directory(&stream_directory).unwrap();
+    info.signature = 0x65EFED7B;
+    info.age = 0x42;
+    info.guid = GUID;
+    info.features.push(PdbFeature::Unknown(0x1337));
+    // Nothing changes in the directory until the flush.
+    let dbi = DbiStream::new(stream_directory.streams[DBI_STREAM_INDEX].clone());
+    assert_ne!(dbi.header().unwrap().get_age(), 0x42);
+    info.flush(&mut stream_directory).unwrap();
+    msf.set_stream_directory(stream_directory).unwrap();
+
+    let stream_directory = msf.get_stream_directory().unwrap();
+    assert_eq!(
+        PdbInfoStream::from_stream_directory(&stream_directory).unwrap(),
+        info
+    );
+    let dbi = DbiStream::new(stream_directory.streams[DBI_STREAM_INDEX].clone());
+    assert_eq!(dbi.header().unwrap().get_age(), 0x42);
+    let mut pdb = pdb::PDB::open(Cursor::new(msf.bytes)).unwrap();
+    let pdb_info = pdb.pdb_information().unwrap();
+    assert_eq!(pdb_info.signature, 0x65EFED7B);
+    assert_eq!(pdb_info.age, 0x42);
+    assert_eq!(pdb_info.guid.as_bytes()[8..], GUID[8..]);
+    assert_eq!(pdb.debug_information().unwrap().age(), Some(0x42));
+    // The named stream map still finds "/names".
+    pdb.string_table().unwrap();
+
+    // Truncated PDB info streams are errors.
+    let bytes = info.to_vec().unwrap();
+    for len in [0, 0x10, 0x1C, 0x20, bytes.len() - 2] {
+        assert!(PdbInfoStream::new(&bytes[..len]).is_err());
+    }
+    let msf = PdbBuilder::new().age(7).build().unwrap();
+    let info = PdbInfoStream::from_stream_directory(&msf.get_stream_directory().unwrap());
+    assert_eq!(info.unwrap().age, 7);
+}
@@ -0,0 +1,98 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::io::Cursor;
+
+use elderscroll::{
+    dbi::DbiStream,
+    directory::{Stream, DBI_STREAM_INDEX},
+    msf::BigMsf,
+    omap::{OmapEntry, OmapStream},
+    pagelist::PageList,
+    pdbsource::PdbSource,
+    view::SourceView,
+};
+use pdb::FallibleIterator;
+
+/// pdb-rs reads the same streams from the adapter as from the file.
+#[test]
+fn pdbsource_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let msf = BigMsf::new(bytes.to_vec());
+    let mut expected = pdb::PDB::open(Cursor::new(bytes.to_vec())).unwrap();
+    let mut pdb = pdb::PDB::open(msf.to_pdb_source().unwrap()).unwrap();
+    let (expected_info, info) = (
+        expected.pdb_information().unwrap(),
+        pdb.pdb_information().unwrap(),
+    );
+    assert_eq!(expected_info.guid, info.guid);
+    assert_eq!(expected_info.age, info.age);
+    assert_eq!(
+        expected.type_information().unwrap().len(),
+        pdb.type_information().unwrap().len()
+    );
+    let expected_symbols = expected.global_symbols().unwrap();
+    let symbols = pdb.global_symbols().unwrap();
+    assert_eq!(
+        expected_symbols.iter().count().unwrap(),
+        symbols.iter().count().unwrap()
+    );
+    let (expected_dbi, dbi) = (
+        expected.debug_information().unwrap(),
+        pdb.debug_information().unwrap(),
+    );
+    let mut modules = dbi.modules().unwrap();
+    let mut expected_modules = expected_dbi.modules().unwrap();
+    while let Some(expected_module) = expected_modules.next().unwrap() {
+        let module = modules.next().unwrap().unwrap();
+        assert_eq!(expected_module.module_name(), module.module_name());
+    }
+}
+
+/// OMAP streams which were never flushed are used by pdb-rs.
+#[test]
+fn pdbsource_test2() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let msf = BigMsf::new(bytes.to_vec());
+    let page_size = msf.header().unwrap().get_page_size();
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let mut dbi = DbiStream::new(stream_directory.streams[DBI_STREAM_INDEX].clone());
+    let omap_stream_index = stream_directory.streams.len() as u16;
+    let mut extras = dbi.extra_streams_mut().unwrap();
+    extras.set_original_section_headers(extras.get_section_headers());
+    extras.set_omap_to_src(omap_stream_index);
+    extras.set_omap_from_src(omap_stream_index + 1);
+    let mut omap_to_src = OmapStream::default();
+    omap_to_src.0.insert(OmapEntry(0x1000, 0x1100));
+    omap_to_src.0.insert(OmapEntry(0x1100, 0x1000));
+    let mut omap_from_src = OmapStream::default();
+    omap_from_src.0.insert(OmapEntry(0x1000, 0x1100));
+    omap_from_src.0.insert(OmapEntry(0x1100, 0x1000));
+    for omap_stream in [omap_to_src, omap_from_src] {
+        stream_directory.streams.push(Stream {
+            original_stream_size: Default::default(),
+            view: SourceView {
+                bytes: omap_stream.to_vec().unwrap(),
+                pages: PageList::new(page_size),
+            },
+        });
+    }
+    stream_directory.streams[DBI_STREAM_INDEX] = dbi.stream;
+
+    let source = PdbSource::new(page_size, stream_directory).unwrap();
+    let mut pdb = pdb::PDB::open(source).unwrap();
+    let address_map = pdb.address_map().unwrap();
+    assert_eq!(
+        pdb::Rva(0x1008).to_internal_rva(&address_map),
+        Some(pdb::PdbInternalRva(0x1108))
+    );
+    assert_eq!(
+        pdb::PdbInternalRva(0x1108).to_rva(&address_map),
+        Some(pdb::Rva(0x1008))
+    );
+}
@@ -0,0 +1,114 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use elderscroll::{
+    builder::PdbBuilder,
+    directory::INVALID_STREAM_SIZE,
+    msf::BigMsf,
+    msfz::{Compression, Msfz},
+};
+
+/// Real PDBs, written by link.exe and by lld-link for x64 and x86.
+const PDBS: [(&str, &[u8]); 4] = [
+    (
+        "HelloWorld.pdb",
+        include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/bins/HelloWorld.pdb"
+        )),
+    ),
+    (
+        "HelloWorld_lld_x64.pdb",
+        include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/bins/HelloWorld_lld_x64.pdb"
+        )),
+    ),
+    (
+        "HelloWorld_lld_x64_8k.pdb",
+        include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/bins/HelloWorld_lld_x64_8k.pdb"
+        )),
+    ),
+    (
+        "HelloWorld_lld_x86.pdb",
+        include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/bins/HelloWorld_lld_x86.pdb"
+        )),
+    ),
+];
+
+/// The real PDBs and a few that elderscroll wrote itself.
+fn pdbs() -> Vec<(String, Vec<u8>)> {
+    let mut pdbs = PDBS
+        .iter()
+        .map(|(name, bytes)| (name.to_string(), bytes.to_vec()))
+        .collect::<Vec<(String, Vec<u8>)>>();
+    let hello_world = BigMsf::new(PDBS[0].1.to_vec())
+        .get_stream_directory()
+        .unwrap();
+    for page_size in [0x200u32, 0x1000, 0x8000] {
+        let msf = BigMsf::from_stream_directory(page_size, hello_world.clone()).unwrap();
+        pdbs.push((format!("HelloWorld.pdb at {page_size:#x}"), msf.bytes));
+        let msf = PdbBuilder::new().page_size(page_size).build().unwrap();
+        pdbs.push((format!("PdbBuilder at {page_size:#x}"), msf.bytes));
+    }
+    pdbs
+}
+
+/// Opening and saving a PDB without any edits gives the same bytes.
+#[test]
+fn roundtrip_test1() {
+    for (name, bytes) in pdbs() {
+        let mut msf = BigMsf::new(bytes.clone());
+        let stream_directory = msf.get_stream_directory().unwrap();
+        msf.set_stream_directory(stream_directory).unwrap();
+        assert!(msf.bytes == bytes, "{name} changed");
+        // Saving it twice does not change it either.
+        let stream_directory = msf.get_stream_directory().unwrap();
+        msf.set_stream_directory(stream_directory).unwrap();
+        assert!(msf.bytes == bytes, "{name} changed on the second save");
+    }
+}
+
+/// Zero length streams stay zero length and nil streams stay nil.
+#[test]
+fn roundtrip_test2() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let sizes = stream_directory
+        .streams
+        .iter()
+        .map(|stream| stream.original_stream_size)
+        .collect::<Vec<u32>>();
+    assert!(sizes.contains(&0));
+    assert!(sizes.contains(&INVALID_STREAM_SIZE));
+    // New empty streams are zero length, removed streams are nil.
+    let empty = stream_directory.add_stream(vec![]);
+    let removed = stream_directory.add_stream(vec![0x69; 0x10]);
+    stream_directory.remove_stream(removed).unwrap();
+    msf.set_stream_directory(stream_directory).unwrap();
+    let mut expected = sizes.clone();
+    expected.extend([0, INVALID_STREAM_SIZE]);
+    let sizes_of = |msf: &BigMsf| {
+        msf.get_stream_directory()
+            .unwrap()
+            .streams
+            .iter()
+            .map(|stream| stream.original_stream_size)
+            .collect::<Vec<u32>>()
+    };
+    assert_eq!(sizes_of(&msf), expected);
+    assert!(!msf.get_stream_directory().unwrap().streams[empty].is_nil());
+    // Every other way of writing the streams keeps them apart as well.
+    let mut compacted = BigMsf::new(msf.bytes.clone());
+    compacted.compact().unwrap();
+    assert_eq!(sizes_of(&compacted), expected);
+    let msfz = Msfz::new(msf.to_msfz(Compression::None).unwrap().bytes);
+    assert_eq!(sizes_of(&msfz.to_big_msf(0x1000).unwrap()), expected);
+}
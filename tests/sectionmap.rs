@@ -0,0 +1,153 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::io::Cursor;
+
+use elderscroll::{
+    builder::PdbBuilder,
+    dbi::{DbiStream, DebugStreamKind},
+    error::Error,
+    msf::BigMsf,
+    omap::OmapStream,
+    sectionmap::{
+        SectionHeader, SectionMap, SECTION_MAP_ADDRESS_IS_32_BIT, SECTION_MAP_EXECUTE,
+        SECTION_MAP_IS_ABSOLUTE_ADDRESS, SECTION_MAP_IS_SELECTOR, SECTION_MAP_READ,
+    },
+};
+
+/// The section map MSVC wrote for HelloWorld.pdb is the one built from its
+/// section headers.
+#[test]
+fn sectionmap_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let stream_directory = BigMsf::new(bytes.to_vec()).get_stream_directory().unwrap();
+    let mut dbi = DbiStream::from_stream_directory(&stream_directory).unwrap();
+    let original = dbi.stream.view.bytes.clone();
+    let section_map = dbi.section_map().unwrap();
+    assert_eq!(section_map.log_count, 7);
+    assert_eq!(section_map.entries.len(), 7);
+    let text = section_map.entries[0];
+    assert_eq!(text.frame, 1);
+    assert_eq!(text.section_length, 0xD6C);
+    assert_eq!(
+        text.flags,
+        SECTION_MAP_READ
+            | SECTION_MAP_EXECUTE
+            | SECTION_MAP_ADDRESS_IS_32_BIT
+            | SECTION_MAP_IS_SELECTOR
+    );
+    let absolute = section_map.entries[6];
+    assert_eq!(
+        absolute.flags,
+        SECTION_MAP_ADDRESS_IS_32_BIT | SECTION_MAP_IS_ABSOLUTE_ADDRESS
+    );
+    assert_eq!(absolute.section_length, u32::MAX);
+
+    let section_headers = dbi
+        .debug_stream(&stream_directory, DebugStreamKind::SectionHeaders)
+        .unwrap()
+        .unwrap();
+    let section_headers = SectionHeader::read_all(section_headers).unwrap();
+    let mut pdb = pdb::PDB::open(Cursor::new(bytes.to_vec())).unwrap();
+    let expected = pdb.sections().unwrap().unwrap();
+    assert_eq!(section_headers.len(), expected.len());
+    for (section_header, expected) in section_headers.iter().zip(expected.iter()) {
+        assert_eq!(section_header.name, expected.name);
+        assert_eq!(section_header.virtual_size, expected.virtual_size);
+        assert_eq!(section_header.virtual_address, expected.virtual_address);
+        assert_eq!(section_header.characteristics, expected.characteristics.0);
+    }
+    assert_eq!(
+        SectionMap::from_section_headers(&section_headers),
+        section_map
+    );
+    dbi.rebuild_section_map(&stream_directory).unwrap();
+    assert_eq!(dbi.stream.view.bytes, original);
+}
+
+/// Rebuild the section map after adding a section, from the original section
+/// headers once the PDB has OMAP and from the section headers before.
+#[test]
+fn sectionmap_test2() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let mut dbi = DbiStream::from_stream_directory(&stream_directory).unwrap();
+    // A new executable section after ".reloc".
+    let mut section_headers = dbi
+        .debug_stream(&stream_directory, DebugStreamKind::SectionHeaders)
+        .unwrap()
+        .unwrap()
+        .to_vec();
+    let mut section_header = section_headers[..0x28].to_vec();
+    section_header[..8].copy_from_slice(b".text2\0\0");
+    section_header[8..12].copy_from_slice(&0x1234u32.to_le_bytes());
+    section_header[12..16].copy_from_slice(&0x7000u32.to_le_bytes());
+    section_headers.extend_from_slice(&section_header);
+    dbi.write_debug_stream(
+        &mut stream_directory,
+        DebugStreamKind::SectionHeaders,
+        section_headers.clone(),
+    )
+    .unwrap();
+    dbi.rebuild_section_map(&stream_directory).unwrap();
+    let section_map = dbi.section_map().unwrap();
+    assert_eq!(section_map.entries.len(), 8);
+    assert_eq!(section_map.entries[6].frame, 7);
+    assert_eq!(section_map.entries[6].section_length, 0x1234);
+    assert_eq!(section_map.entries[6].flags, section_map.entries[0].flags);
+
+    // The original section headers win once there are some.
+    let original = dbi
+        .debug_stream(&stream_directory, DebugStreamKind::SectionHeaders)
+        .unwrap()
+        .unwrap()[..0x28 * 6]
+        .to_vec();
+    dbi.write_debug_stream(
+        &mut stream_directory,
+        DebugStreamKind::OriginalSectionHeaders,
+        original,
+    )
+    .unwrap();
+    dbi.set_omap(
+        &mut stream_directory,
+        &OmapStream::default(),
+        &OmapStream::default(),
+    )
+    .unwrap();
+    dbi.rebuild_section_map(&stream_directory).unwrap();
+    assert_eq!(dbi.section_map().unwrap().entries.len(), 7);
+    dbi.write_debug_stream(
+        &mut stream_directory,
+        DebugStreamKind::OriginalSectionHeaders,
+        section_headers,
+    )
+    .unwrap();
+    dbi.rebuild_section_map(&stream_directory).unwrap();
+    dbi.flush(&mut stream_directory).unwrap();
+    msf.set_stream_directory(stream_directory).unwrap();
+
+    let stream_directory = msf.get_stream_directory().unwrap();
+    let dbi = DbiStream::from_stream_directory(&stream_directory).unwrap();
+    assert_eq!(dbi.section_map().unwrap(), section_map);
+    let mut pdb = pdb::PDB::open(Cursor::new(msf.bytes)).unwrap();
+    pdb.debug_information().unwrap();
+
+    // Section headers are needed, and must be whole.
+    let msf = PdbBuilder::new().build().unwrap();
+    let stream_directory = msf.get_stream_directory().unwrap();
+    let mut dbi = DbiStream::from_stream_directory(&stream_directory).unwrap();
+    assert!(matches!(
+        dbi.rebuild_section_map(&stream_directory),
+        Err(Error::MissingDebugStream(_))
+    ));
+    assert!(SectionHeader::read_all(&[0; 0x27]).is_err());
+    let bytes = section_map.to_vec().unwrap();
+    assert!(SectionMap::new(&bytes[..bytes.len() - 1]).is_err());
+    assert!(SectionMap::new(&bytes[..2]).is_err());
+}
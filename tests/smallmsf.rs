@@ -0,0 +1,104 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use elderscroll::{
+    directory::INVALID_STREAM_SIZE,
+    error::Error,
+    msf::BigMsf,
+    smallmsf::{SmallMsf, SMALL_DIRECTORY_PAGES_OFFSET, SMALL_MAGIC},
+};
+
+/// Write a PDB 2.0 file with the given streams laid out one after another.
+fn write_small_msf(page_size: usize, streams: &[(u32, Vec<u8>)]) -> Vec<u8> {
+    let mut bytes = vec![0u8; page_size * 3];
+    let mut pages = Vec::<u16>::new();
+    let write_pages = |bytes: &mut Vec<u8>, data: &[u8], pages: &mut Vec<u16>| {
+        for chunk in data.chunks(page_size) {
+            pages.push((bytes.len() / page_size) as u16);
+            bytes.extend_from_slice(chunk);
+            bytes.resize(bytes.len().next_multiple_of(page_size), 0);
+        }
+    };
+    for (_, data) in streams.iter() {
+        write_pages(&mut bytes, data, &mut pages);
+    }
+    // NumStreams, reserved, then size + reserved for every stream and all the pages.
+    let mut directory = Vec::<u8>::new();
+    directory.extend_from_slice(&(streams.len() as u16).to_le_bytes());
+    directory.extend_from_slice(&[0u8; 2]);
+    for (size, _) in streams.iter() {
+        directory.extend_from_slice(&size.to_le_bytes());
+        directory.extend_from_slice(&[0u8; 4]);
+    }
+    for pfn in pages.iter() {
+        directory.extend_from_slice(&pfn.to_le_bytes());
+    }
+    let mut directory_pages = Vec::<u16>::new();
+    write_pages(&mut bytes, &directory, &mut directory_pages);
+    bytes[..SMALL_MAGIC.len()].copy_from_slice(SMALL_MAGIC);
+    bytes[0x2C..0x30].copy_from_slice(&(page_size as u32).to_le_bytes());
+    bytes[0x30..0x32].copy_from_slice(&1u16.to_le_bytes());
+    let num_pages = (bytes.len() / page_size) as u16;
+    bytes[0x32..0x34].copy_from_slice(&num_pages.to_le_bytes());
+    bytes[0x34..0x38].copy_from_slice(&(directory.len() as u32).to_le_bytes());
+    for (i, pfn) in directory_pages.iter().enumerate() {
+        let offset = SMALL_DIRECTORY_PAGES_OFFSET + i * 2;
+        bytes[offset..offset + 2].copy_from_slice(&pfn.to_le_bytes());
+    }
+    bytes
+}
+
+/// Move every stream of HelloWorld.pdb into a small MSF, read it back and upgrade it.
+#[test]
+fn smallmsf_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let msf = BigMsf::new(bytes.to_vec());
+    let stream_directory = msf.get_stream_directory().unwrap();
+    let streams = stream_directory
+        .streams
+        .iter()
+        .map(|stream| (stream.original_stream_size, stream.view.bytes.clone()))
+        .collect::<Vec<_>>();
+    let small_msf = SmallMsf::new(write_small_msf(0x1000, &streams));
+    let small_directory = small_msf.get_stream_directory().unwrap();
+    assert_eq!(small_directory.streams.len(), streams.len());
+    for (stream, (size, bytes)) in small_directory.streams.iter().zip(streams.iter()) {
+        assert_eq!(stream.original_stream_size, *size);
+        assert_eq!(&stream.view.bytes, bytes);
+    }
+    // Upgrade it and make sure every stream survived.
+    let big_msf = small_msf.to_big_msf().unwrap();
+    let header = big_msf.header().unwrap();
+    assert_eq!(header.get_page_size(), 0x1000);
+    assert_eq!(
+        header.get_num_pages() * header.get_page_size(),
+        big_msf.bytes.len() as u32
+    );
+    let big_directory = big_msf.get_stream_directory().unwrap();
+    assert_eq!(big_directory.streams.len(), streams.len());
+    for (stream, (size, bytes)) in big_directory.streams.iter().zip(streams.iter()) {
+        if *size == INVALID_STREAM_SIZE || *size == 0 {
+            assert!(stream.view.bytes.is_empty());
+        } else {
+            assert_eq!(stream.original_stream_size, *size);
+            assert_eq!(&stream.view.bytes, bytes);
+        }
+    }
+}
+
+/// Big MSF files are not small MSF files.
+#[test]
+fn smallmsf_test2() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let small_msf = SmallMsf::new(bytes.to_vec());
+    assert!(matches!(small_msf.header(), Err(Error::BadMagic)));
+    assert!(matches!(
+        small_msf.get_stream_directory(),
+        Err(Error::BadMagic)
+    ));
+}
@@ -0,0 +1,142 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use std::io::Cursor;
+
+use elderscroll::{
+    builder::PdbBuilder,
+    dbi::DbiStream,
+    directory::DBI_STREAM_INDEX,
+    error::Error,
+    msf::BigMsf,
+    pdbinfo::PdbInfoStream,
+    stringtable::{StringTable, STRING_TABLE_NAME},
+};
+use pdb::StringRef;
+
+/// Parse "/names" of HelloWorld.pdb, every string is found by offset and by
+/// string like the pdb crate finds it, and it writes back the same bytes.
+#[test]
+fn stringtable_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let stream_directory = BigMsf::new(bytes.to_vec()).get_stream_directory().unwrap();
+    let table = StringTable::from_stream_directory(&stream_directory).unwrap();
+    let mut pdb = pdb::PDB::open(Cursor::new(bytes.to_vec())).unwrap();
+    let expected = pdb.string_table().unwrap();
+    assert_eq!(table.len(), 200);
+    assert_eq!(table.iter().count(), 200);
+    for (offset, string) in table.iter() {
+        assert_eq!(expected.get(StringRef(offset)).unwrap().as_bytes(), string);
+        assert_eq!(table.find(string), Some(offset));
+    }
+    // MSVC hashes an empty string too, tables which do not still find it at 0.
+    assert_eq!(table.get_str(table.find(b"").unwrap()), Some(""));
+    assert_eq!(StringTable::default().find(b""), Some(0));
+    assert_eq!(table.find(b"C:\\does\\not\\exist.cpp"), None);
+    assert_eq!(table.get(u32::MAX), None);
+    assert_eq!(
+        table.to_vec().unwrap(),
+        stream_directory.streams[7].view.bytes
+    );
+
+    // The EC substream of the DBI stream is a string table too.
+    let dbi = DbiStream::new(stream_directory.streams[DBI_STREAM_INDEX].clone());
+    let header = dbi.header().unwrap();
+    let offset = 0x40
+        + header.get_mod_info_size() as usize
+        + header.get_section_contribution_size() as usize
+        + header.get_section_map_size() as usize
+        + header.get_source_info_size() as usize
+        + header.get_type_server_map_size() as usize;
+    let ec = &dbi.stream.view.bytes[offset..offset + header.get_ec_substream_size() as usize];
+    assert_eq!(StringTable::new(ec).unwrap().to_vec().unwrap(), ec);
+}
+
+/// Append strings until the hash table grows, save the PDB and read every
+/// string back. Strings already in the table keep their offsets.
+#[test]
+fn stringtable_test2() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut msf = BigMsf::new(bytes.to_vec());
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let mut table = StringTable::from_stream_directory(&stream_directory).unwrap();
+    let original = table.iter().map(|(offset, _)| offset).collect::<Vec<u32>>();
+    let strings = (0..500)
+        .map(|i| format!("C:\\remapped\\{i}.cpp"))
+        .collect::<Vec<String>>();
+    let offsets = strings
+        .iter()
+        .map(|string| table.insert(string.as_bytes()))
+        .collect::<Vec<u32>>();
+    assert!(table.buckets.len() > 314);
+    assert_eq!(table.insert(strings[0].as_bytes()), offsets[0]);
+    assert_eq!(table.len(), 700);
+    table.flush(&mut stream_directory).unwrap();
+    msf.set_stream_directory(stream_directory).unwrap();
+
+    let stream_directory = msf.get_stream_directory().unwrap();
+    let saved = StringTable::from_stream_directory(&stream_directory).unwrap();
+    assert_eq!(saved, table);
+    let mut pdb = pdb::PDB::open(Cursor::new(msf.bytes)).unwrap();
+    let expected = pdb.string_table().unwrap();
+    for (string, offset) in strings.iter().zip(offsets) {
+        assert_eq!(saved.find(string.as_bytes()), Some(offset));
+        assert_eq!(
+            expected.get(StringRef(offset)).unwrap().to_string(),
+            *string
+        );
+    }
+    let offsets = saved.iter().map(|(offset, _)| offset).collect::<Vec<u32>>();
+    assert_eq!(offsets[..original.len()], original);
+
+    // A PDB without "/names" gets a new stream for it.
+    let mut msf = PdbBuilder::new().build().unwrap();
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    let mut info = PdbInfoStream::from_stream_directory(&stream_directory).unwrap();
+    info.named_stream_map.remove(STRING_TABLE_NAME);
+    info.flush(&mut stream_directory).unwrap();
+    assert!(matches!(
+        StringTable::from_stream_directory(&stream_directory),
+        Err(Error::MissingNamedStream(_))
+    ));
+    let mut table = StringTable::default();
+    let offset = table.insert(b"/natvis");
+    table.flush(&mut stream_directory).unwrap();
+    msf.set_stream_directory(stream_directory).unwrap();
+    let stream_directory = msf.get_stream_directory().unwrap();
+    let saved = StringTable::from_stream_directory(&stream_directory).unwrap();
+    assert_eq!(saved.get_str(offset), Some("/natvis"));
+
+    // Paths are in the ANSI code page, "é" is 0xE9 in Windows-1252.
+    let ansi = b"C:\\caf\xE9\\main.cpp";
+    let mut table = saved.clone();
+    let ansi_offset = table.insert(ansi);
+    let table = StringTable::new(&table.to_vec().unwrap()).unwrap();
+    assert_eq!(table.get(ansi_offset), Some(&ansi[..]));
+    assert_eq!(table.get_str(ansi_offset), None);
+    assert_eq!(table.find(ansi), Some(ansi_offset));
+    assert_eq!(table.iter().count(), 2);
+
+    // Truncated and corrupted string tables are errors.
+    let bytes = saved.to_vec().unwrap();
+    for len in 0..bytes.len() {
+        assert!(StringTable::new(&bytes[..len]).is_err());
+    }
+    let mut corrupted = bytes.clone();
+    corrupted[0] = 0;
+    let result = StringTable::new(&corrupted);
+    assert!(matches!(result, Err(Error::BadSignature { .. })));
+    let mut corrupted = bytes.clone();
+    corrupted[4] = 3;
+    assert!(StringTable::new(&corrupted).unwrap_err().is_unsupported());
+    let mut corrupted = bytes.clone();
+    let len = corrupted.len();
+    corrupted[len - 4] = 2;
+    let result = StringTable::new(&corrupted);
+    assert!(matches!(result, Err(Error::BadHashTable { .. })));
+}
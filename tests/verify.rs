@@ -0,0 +1,110 @@
+// Copyright (C) Back Engineering Labs, Inc. - All Rights Reserved
+
+use elderscroll::{
+    builder::PdbBuilder,
+    directory::DBI_STREAM_INDEX,
+    msf::BigMsf,
+    verify::{Finding, PageOwner},
+};
+
+/// Real and freshly written files have no findings.
+#[test]
+fn verify_test1() {
+    let bytes = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/bins/HelloWorld.pdb"
+    ));
+    let mut msf = BigMsf::new(bytes.to_vec());
+    assert_eq!(msf.verify(), vec![]);
+    let mut stream_directory = msf.get_stream_directory().unwrap();
+    stream_directory.streams[DBI_STREAM_INDEX]
+        .view
+        .bytes
+        .resize(0x3000, 0x69);
+    msf.set_stream_directory(stream_directory).unwrap();
+    assert_eq!(msf.verify(), vec![]);
+    let msf = PdbBuilder::new().page_size(0x200).build().unwrap();
+    assert_eq!(msf.verify(), vec![]);
+}
+
+/// Corrupt a synthetic PDB in different ways.
+#[test]
+fn verify_test2() {
+    let msf = PdbBuilder::new().build().unwrap();
+    let header = msf.header().unwrap();
+    let page_size = header.get_page_size() as usize;
+    let num_pages = header.get_num_pages();
+    let stream_directory = msf.get_stream_directory().unwrap();
+    let dir_page = stream_directory.view.pages.pfns[0] as usize * page_size;
+    let stream1_page = stream_directory.streams[1].view.pages.pfns[0];
+    let stream2_page = stream_directory.streams[2].view.pages.pfns[0];
+
+    // File is longer than the header says.
+    let mut bytes = msf.bytes.clone();
+    bytes.push(0);
+    assert_eq!(
+        BigMsf::new(bytes).verify(),
+        vec![Finding::FileLengthMismatch {
+            expected: num_pages as u64 * page_size as u64,
+            actual: num_pages as u64 * page_size as u64 + 1,
+        }]
+    );
+
+    // Stream 2 uses the page of stream 1, its own page is leaked.
+    let mut bytes = msf.bytes.clone();
+    // Number of streams, 6 sizes, then the page of stream 1 and 2.
+    let offset = dir_page + 4 + 6 * 4 + 4;
+    bytes[offset..offset + 4].copy_from_slice(&stream1_page.to_le_bytes());
+    assert_eq!(
+        BigMsf::new(bytes).verify(),
+        vec![
+            Finding::DuplicatePage {
+                pfn: stream1_page,
+                first: PageOwner::Stream(1),
+                second: PageOwner::Stream(2),
+            },
+            Finding::FreePageMarkedUsed { pfn: stream2_page },
+        ]
+    );
+
+    // Page past the end of the file.
+    let mut bytes = msf.bytes.clone();
+    bytes[offset..offset + 4].copy_from_slice(&num_pages.to_le_bytes());
+    let findings = BigMsf::new(bytes).verify();
+    assert!(findings.contains(&Finding::PageOutOfRange {
+        owner: PageOwner::Stream(2),
+        pfn: num_pages,
+    }));
+
+    // Page in use but free in the FPM.
+    let mut bytes = msf.bytes.clone();
+    let fpm_page = header.get_free_page_map() as usize * page_size;
+    bytes[fpm_page + stream1_page as usize / 8] |= 1 << (stream1_page % 8);
+    assert_eq!(
+        BigMsf::new(bytes).verify(),
+        vec![Finding::UsedPageMarkedFree {
+            pfn: stream1_page,
+            owner: PageOwner::Stream(1),
+        }]
+    );
+
+    // Directory size is too big.
+    let mut bytes = msf.bytes.clone();
+    let dir_size = header.get_stream_dir_size();
+    bytes[0x2C..0x30].copy_from_slice(&(dir_size + 4).to_le_bytes());
+    assert_eq!(
+        BigMsf::new(bytes).verify(),
+        vec![Finding::DirectorySizeMismatch {
+            expected: dir_size,
+            actual: dir_size + 4,
+        }]
+    );
+
+    // Bad magic.
+    let mut bytes = msf.bytes.clone();
+    bytes[0] = 0;
+    assert!(matches!(
+        BigMsf::new(bytes).verify().as_slice(),
+        [Finding::BadHeader(_)]
+    ));
+}